@@ -34,26 +34,32 @@ fn main() {
 
 
 
-    if target_os == "macos" {
-        println!("Building for macOS");
+    if target_os == "macos" || target_os == "linux" {
+        println!("Building for {}", if target_os == "macos" { "macOS" } else { "Linux" });
 
         println!("cargo:rustc-link-lib=cups");
 
-        let sdk_path = std::process::Command::new("xcrun")
-            .args(&["--sdk", "macosx", "--show-sdk-path"])
-            .output()
-            .expect("xcrun failed")
-            .stdout;
+        let mut builder = bindgen::Builder::default()
+            .header("wrapper_cups.h")
+            .clang_arg("-I./include");
 
-        let sdk_root = String::from_utf8(sdk_path).expect("Invalid UTF-8 in SDK path");
-        let sdk_root = sdk_root.trim();
+        if target_os == "macos" {
+            let sdk_path = std::process::Command::new("xcrun")
+                .args(&["--sdk", "macosx", "--show-sdk-path"])
+                .output()
+                .expect("xcrun failed")
+                .stdout;
 
+            let sdk_root = String::from_utf8(sdk_path).expect("Invalid UTF-8 in SDK path");
+            let sdk_root = sdk_root.trim();
 
-        let bindings = bindgen::Builder::default()
-            .header("wrapper_cups.h")
-            .clang_arg("-I./include")
-            .clang_arg(format!("-isysroot{}", sdk_root))
-            // .clang_arg("-I/usr/include")
+            builder = builder.clang_arg(format!("-isysroot{}", sdk_root));
+        } else {
+            // libcups-dev installs the headers under /usr/include/cups
+            builder = builder.clang_arg("-I/usr/include");
+        }
+
+        let bindings = builder
             .generate()
             .expect("Unable to generate bindings");
 
@@ -0,0 +1,218 @@
+use std::path::PathBuf;
+
+use crate::printer::{FitMode, Slot};
+
+
+pub const USAGE: &str = "\
+Usage:
+  photo_qt                          Start the GUI
+  photo_qt --headless [OPTIONS]     Watch a folder and print without a window
+  photo_qt compose [OPTIONS]        Compose photos into a template once
+
+Headless options:
+  --config <FILE>     JSON file with watch_dirs, watch, upload, profile, layout
+                      and print
+  --watch <DIR>       Folder to watch for new photos, repeat for more folders
+  --recursive         Also watch subfolders
+  --include <GLOB>    Only print photos matching the pattern, can be repeated
+  --exclude <GLOB>    Skip photos matching the pattern, can be repeated
+  --print-existing    Also print the photos already in the folders, oldest first
+  --poll              Poll the folders instead of waiting for file events, for
+                      network shares that aren't detected as such
+  --poll-interval <SECS>  Seconds between polls, 2 by default
+  --upload-port <PORT>  Also take photo uploads over HTTP on this port from
+                      the local network, stored in the first watched folder
+  --profile <NAME>    Saved layout profile to print with
+  --printer <NAME>    Printer name or ipp:// uri, overrides the profile's
+
+Compose options:
+  --image <FILE>      Photo to place, repeat for one photo per slot
+  --template <PDF>    Template to place the photos on
+  --slot <X,Y,W,H>    Photo box in PDF points, repeat for more slots
+  --page <N>          Template page of the following --slot options (from 1)
+  --fit <MODE>        contain, cover, focus or stretch for the following --slot
+                      options
+  --focus <X,Y>       Point of the photo --fit focus keeps in view, as fractions
+                      from its top-left corner, for the following --slot options
+  --rotation <DEG>    Counterclockwise rotation for the following --slot options
+  --repeat            Put the first photo into every slot
+  --profile <NAME>    Take template and slots from a saved profile instead
+  --output <FILE>     Where to write the PDF, defaults to the output folder
+  --print             Also send the PDF to the printer
+  --printer <NAME>    Printer name or ipp:// uri, defaults to the system's
+  --copies <N>        Number of copies to print
+
+  -h, --help          Show this help";
+
+pub enum Command {
+    Gui,
+    Headless(HeadlessArgs),
+    Compose(ComposeArgs),
+    Help,
+}
+
+/// Flags given on the command line; they override the config file.
+#[derive(Debug, Default)]
+pub struct HeadlessArgs {
+    pub config: Option<PathBuf>,
+    pub watch_dirs: Vec<PathBuf>,
+    pub recursive: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub print_existing: bool,
+    pub poll: bool,
+    pub poll_interval: Option<u64>,
+    pub upload_port: Option<u16>,
+    pub profile: Option<String>,
+    pub printer: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ComposeArgs {
+    pub images: Vec<String>,
+    pub template: Option<String>,
+    pub slots: Vec<Slot>,
+    pub repeat: bool,
+    pub profile: Option<String>,
+    pub output: Option<PathBuf>,
+    pub print: bool,
+    pub printer: Option<String>,
+    pub copies: Option<u32>,
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(String::as_str) == Some("compose") {
+        args.next();
+        return parse_compose(args);
+    }
+    let mut headless = None;
+    let mut flags = HeadlessArgs::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--headless" => headless = Some(()),
+            "--config" => flags.config = Some(PathBuf::from(value(&arg)?)),
+            "--watch" => flags.watch_dirs.push(PathBuf::from(value(&arg)?)),
+            "--recursive" => flags.recursive = true,
+            "--include" => flags.include.push(value(&arg)?),
+            "--exclude" => flags.exclude.push(value(&arg)?),
+            "--print-existing" => flags.print_existing = true,
+            "--poll" => flags.poll = true,
+            "--poll-interval" => flags.poll_interval = Some(parse_number(&arg, &value(&arg)?)?),
+            "--upload-port" => flags.upload_port = Some(parse_number(&arg, &value(&arg)?)?),
+            "--profile" => flags.profile = Some(value(&arg)?),
+            "--printer" => flags.printer = Some(value(&arg)?),
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    match headless {
+        Some(()) => Ok(Command::Headless(flags)),
+        None if flags.config.is_some()
+            || !flags.watch_dirs.is_empty()
+            || flags.recursive
+            || !flags.include.is_empty()
+            || !flags.exclude.is_empty()
+            || flags.print_existing
+            || flags.poll
+            || flags.poll_interval.is_some()
+            || flags.upload_port.is_some()
+            || flags.profile.is_some()
+            || flags.printer.is_some() =>
+        {
+            Err("Headless options only apply with --headless".to_string())
+        }
+        None => Ok(Command::Gui),
+    }
+}
+
+fn parse_compose(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut compose = ComposeArgs::default();
+    // --page, --fit, --focus and --rotation apply to the slots given after them
+    let mut page = 0;
+    let mut fit = FitMode::default();
+    let mut focus = (0.5, 0.5);
+    let mut rotation = 0.0;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--image" => compose.images.push(value(&arg)?),
+            "--template" => compose.template = Some(value(&arg)?),
+            "--slot" => {
+                let mut slot = parse_slot(&value(&arg)?)?;
+                slot.page = page;
+                slot.fit = fit;
+                slot.focus = focus;
+                slot.rotation = rotation;
+                compose.slots.push(slot);
+            }
+            "--page" => {
+                let number: usize = parse_number(&arg, &value(&arg)?)?;
+                page = number.checked_sub(1).ok_or("--page counts from 1")?;
+            }
+            "--fit" => fit = parse_fit(&value(&arg)?)?,
+            "--focus" => focus = parse_focus(&value(&arg)?)?,
+            "--rotation" => rotation = parse_number(&arg, &value(&arg)?)?,
+            "--repeat" => compose.repeat = true,
+            "--profile" => compose.profile = Some(value(&arg)?),
+            "--output" => compose.output = Some(PathBuf::from(value(&arg)?)),
+            "--print" => compose.print = true,
+            "--printer" => compose.printer = Some(value(&arg)?),
+            "--copies" => compose.copies = Some(parse_number(&arg, &value(&arg)?)?),
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    if compose.images.is_empty() {
+        return Err("compose needs at least one --image".to_string());
+    }
+    match (&compose.profile, &compose.template) {
+        (Some(_), Some(_)) => return Err("--profile and --template can't be combined".to_string()),
+        (Some(_), None) if !compose.slots.is_empty() => return Err("--slot can't be combined with --profile".to_string()),
+        (None, None) => return Err("compose needs a --template or a --profile".to_string()),
+        (None, Some(_)) if compose.slots.is_empty() => return Err("compose needs at least one --slot".to_string()),
+        _ => {}
+    }
+    Ok(Command::Compose(compose))
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", name, value))
+}
+
+fn parse_slot(value: &str) -> Result<Slot, String> {
+    let numbers = value
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("Invalid slot {:?}, expected X,Y,WIDTH,HEIGHT", value))?;
+    match numbers[..] {
+        [x, y, width, height] => Ok(Slot::new(x, y, width, height)),
+        _ => Err(format!("Invalid slot {:?}, expected X,Y,WIDTH,HEIGHT", value)),
+    }
+}
+
+fn parse_fit(value: &str) -> Result<FitMode, String> {
+    match value {
+        "contain" => Ok(FitMode::Contain),
+        "cover" => Ok(FitMode::Cover),
+        "focus" => Ok(FitMode::Focus),
+        "stretch" => Ok(FitMode::Stretch),
+        _ => Err(format!("Unknown fit mode {:?}, expected contain, cover, focus or stretch", value)),
+    }
+}
+
+fn parse_focus(value: &str) -> Result<(f32, f32), String> {
+    let invalid = || format!("Invalid focus {:?}, expected X,Y between 0 and 1", value);
+    let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+    let (x, y): (f32, f32) = (x.trim().parse().map_err(|_| invalid())?, y.trim().parse().map_err(|_| invalid())?);
+    if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+        return Err(invalid());
+    }
+    Ok((x, y))
+}
@@ -0,0 +1,43 @@
+use std::error::Error;
+
+use crate::cli::ComposeArgs;
+use crate::printer::{PdfImageInserter, PrintSettings, SlotFill};
+use crate::printer_wrapper::make_printer;
+use crate::profiles::Profiles;
+
+
+/// Composes the given photos into one PDF, and prints it if asked to.
+pub fn run(args: ComposeArgs) -> Result<(), Box<dyn Error>> {
+    let (mut layout, mut print) = match (&args.profile, args.template) {
+        (Some(name), _) => {
+            let profiles = Profiles::open(Profiles::default_path())?;
+            let profile = profiles.get(name).ok_or_else(|| format!("No saved profile named {:?}", name))?;
+            (profile.layout.clone(), profile.print.clone())
+        }
+        (None, Some(template)) => (PdfImageInserter::new(template, args.slots), PrintSettings::default()),
+        (None, None) => return Err("compose needs a --template or a --profile".into()),
+    };
+    if args.repeat {
+        layout = layout.with_fill(SlotFill::Repeat);
+    }
+    if args.printer.is_some() {
+        print.printer_name = args.printer;
+    }
+    if let Some(copies) = args.copies {
+        print.options.copies = copies;
+    }
+
+    let output_path = match args.output {
+        Some(path) => path,
+        None => layout.save_pdf_path(&args.images[0])?,
+    };
+    layout.insert_image(&args.images, &output_path)?;
+    println!("Wrote {:?}", output_path);
+
+    if args.print {
+        let printer = make_printer(print.printer_name.as_deref())?;
+        let job = printer.print(&output_path, &print.options)?;
+        println!("Submitted print job {}", job.id);
+    }
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    thread,
+};
+
+use crate::cli::HeadlessArgs;
+use crate::journal::Journal;
+use crate::ledger::Ledger;
+use crate::printer::{CancelRequests, PdfImageInserter, PrintSettings};
+use crate::profiles::Profiles;
+use crate::upload::{UploadJobs, UploadServer, UploadSettings};
+use crate::watcher::{FolderWatcher, WatchBackend, WatchSettings};
+
+
+/// Contents of the `--config` file. A `layout` or `print` given here wins
+/// over the one from `profile`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct HeadlessConfig {
+    watch_dirs: Vec<PathBuf>,
+    watch: WatchSettings,
+    /// Print the photos that are already in the folders when starting.
+    print_existing: bool,
+    /// Also take photo uploads over HTTP, stored in the first of `watch_dirs`.
+    upload: Option<UploadSettings>,
+    profile: Option<String>,
+    layout: Option<PdfImageInserter>,
+    print: Option<PrintSettings>,
+}
+
+impl HeadlessConfig {
+    fn load(path: &Path) -> Result<HeadlessConfig, Box<dyn Error>> {
+        let raw = fs::read_to_string(path).map_err(|e| format!("Failed to read config {:?}: {}", path, e))?;
+        Ok(serde_json::from_str(&raw).map_err(|e| format!("Failed to parse config {:?}: {}", path, e))?)
+    }
+}
+
+/// Runs the watcher and inserter pipeline until the process is stopped.
+pub fn run(args: HeadlessArgs) -> Result<(), Box<dyn Error>> {
+    let mut config = match &args.config {
+        Some(path) => HeadlessConfig::load(path)?,
+        None => HeadlessConfig::default(),
+    };
+    if !args.watch_dirs.is_empty() {
+        config.watch_dirs = args.watch_dirs;
+    }
+    config.watch.recursive |= args.recursive;
+    config.watch.include.extend(args.include);
+    config.watch.exclude.extend(args.exclude);
+    config.print_existing |= args.print_existing;
+    if args.poll {
+        config.watch.backend = WatchBackend::Polling;
+    }
+    if let Some(secs) = args.poll_interval {
+        config.watch.poll_interval_secs = secs;
+    }
+    if let Some(port) = args.upload_port {
+        config.upload.get_or_insert_with(UploadSettings::default).port = port;
+    }
+    if args.profile.is_some() {
+        config.profile = args.profile;
+    }
+
+    let profile = match &config.profile {
+        Some(name) => {
+            let profiles = Profiles::open(Profiles::default_path())?;
+            Some(profiles.get(name).cloned().ok_or_else(|| format!("No saved profile named {:?}", name))?)
+        }
+        None => None,
+    };
+    let layout = config
+        .layout
+        .or_else(|| profile.as_ref().map(|p| p.layout.clone()))
+        .ok_or("No layout to print with, pass --profile or set layout in the config file")?;
+    let mut print = config
+        .print
+        .or_else(|| profile.as_ref().map(|p| p.print.clone()))
+        .unwrap_or_default();
+    if args.printer.is_some() {
+        print.printer_name = args.printer;
+    }
+    if config.watch_dirs.is_empty() {
+        return Err("No folder to watch, pass --watch or set watch_dirs in the config file".into());
+    }
+
+    println!(
+        "Printing new photos from {:?} with template {} on {}",
+        config.watch_dirs,
+        layout.template_path(),
+        print.printer_name.as_deref().unwrap_or("the default printer")
+    );
+
+    let (status_tx, status_rx) = mpsc::channel();
+    let ledger = Arc::new(Mutex::new(Ledger::open_or_reset(Ledger::default_path())));
+    // Nobody to cancel retrying jobs, they are tried until the printer is back
+    let inserter_tx = PdfImageInserter::spawn(
+        Arc::new(Mutex::new(layout)),
+        Arc::new(Mutex::new(print)),
+        ledger,
+        CancelRequests::default(),
+        status_tx,
+    )?;
+    let upload_jobs = UploadJobs::default();
+    let status_jobs = upload_jobs.clone();
+    thread::spawn(move || {
+        for update in status_rx {
+            println!("Print status of {}: {}", update.image_path, update.status.label());
+            status_jobs.update(&update);
+        }
+    });
+
+    let (tx, rx) = mpsc::channel();
+    let mut folder_watcher = FolderWatcher::new(tx.clone());
+    let existing = folder_watcher
+        .spawn_watcher(&config.watch_dirs, &config.watch)
+        .map_err(|e| format!("Failed to watch folder: {}", e))?;
+
+    // Kept alive for as long as the pipeline runs
+    let _upload_server = match &config.upload {
+        Some(settings) => Some(
+            UploadServer::spawn(settings, config.watch_dirs[0].clone(), tx, folder_watcher.claimed(), upload_jobs)
+                .map_err(|e| format!("Failed to accept uploads on port {}: {}", settings.port, e))?,
+        ),
+        None => {
+            drop(tx);
+            None
+        }
+    };
+
+    if config.print_existing {
+        // Photos of unfinished jobs are already being printed again by the inserter
+        let journal = Journal::open(Journal::default_path())?;
+        for path in existing {
+            if journal.entries().iter().any(|e| e.image_paths.contains(&path)) {
+                continue;
+            }
+            println!("Existing image: {}", path);
+            inserter_tx.send(path).map_err(|_| "The inserter thread stopped")?;
+        }
+    } else if !existing.is_empty() {
+        println!("Leaving {} photos already in the watched folders alone, pass --print-existing to print them", existing.len());
+    }
+
+    for path in rx {
+        println!("New image: {}", path);
+        inserter_tx.send(path).map_err(|_| "The inserter thread stopped")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,618 @@
+use std::{
+    error::Error,
+    io::{Read, Write},
+    net::TcpStream,
+    sync::atomic::{AtomicI32, Ordering},
+    time::Duration,
+};
+
+pub const DEFAULT_PORT: u16 = 631;
+
+const IPP_VERSION: [u8; 2] = [1, 1];
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+static NEXT_REQUEST_ID: AtomicI32 = AtomicI32::new(1);
+
+pub mod operation {
+    pub const PRINT_JOB: u16 = 0x0002;
+    pub const GET_JOB_ATTRIBUTES: u16 = 0x0009;
+    pub const GET_PRINTER_ATTRIBUTES: u16 = 0x000B;
+}
+
+pub mod status {
+    pub const CLIENT_ERROR_NOT_FOUND: u16 = 0x0406;
+}
+
+pub mod tag {
+    pub const OPERATION_ATTRIBUTES: u8 = 0x01;
+    pub const JOB_ATTRIBUTES: u8 = 0x02;
+    pub const END_OF_ATTRIBUTES: u8 = 0x03;
+    pub const PRINTER_ATTRIBUTES: u8 = 0x04;
+
+    pub const INTEGER: u8 = 0x21;
+    pub const BOOLEAN: u8 = 0x22;
+    pub const ENUM: u8 = 0x23;
+    pub const TEXT_WITHOUT_LANGUAGE: u8 = 0x41;
+    pub const NAME_WITHOUT_LANGUAGE: u8 = 0x42;
+    pub const KEYWORD: u8 = 0x44;
+    pub const URI: u8 = 0x45;
+    pub const CHARSET: u8 = 0x47;
+    pub const NATURAL_LANGUAGE: u8 = 0x48;
+    pub const MIME_MEDIA_TYPE: u8 = 0x49;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IppValue {
+    Integer(i32),
+    Boolean(bool),
+    Enum(i32),
+    Text(String),
+    Other(u8, Vec<u8>),
+}
+
+impl IppValue {
+    fn encode(&self) -> (u8, Vec<u8>) {
+        match self {
+            IppValue::Integer(v) => (tag::INTEGER, v.to_be_bytes().to_vec()),
+            IppValue::Boolean(v) => (tag::BOOLEAN, vec![*v as u8]),
+            IppValue::Enum(v) => (tag::ENUM, v.to_be_bytes().to_vec()),
+            IppValue::Text(v) => (tag::TEXT_WITHOUT_LANGUAGE, v.as_bytes().to_vec()),
+            IppValue::Other(t, bytes) => (*t, bytes.clone()),
+        }
+    }
+
+    fn decode(value_tag: u8, bytes: &[u8]) -> IppValue {
+        match (value_tag, bytes.len()) {
+            (tag::INTEGER, 4) => IppValue::Integer(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            (tag::ENUM, 4) => IppValue::Enum(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            (tag::BOOLEAN, 1) => IppValue::Boolean(bytes[0] != 0),
+            (0x41..=0x49, _) => IppValue::Text(String::from_utf8_lossy(bytes).into_owned()),
+            _ => IppValue::Other(value_tag, bytes.to_vec()),
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            IppValue::Integer(v) | IppValue::Enum(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            IppValue::Text(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// String values are written with the given syntax tag (keyword, uri, ...)
+/// rather than plain text.
+fn tagged(value_tag: u8, value: &str) -> IppValue {
+    IppValue::Other(value_tag, value.as_bytes().to_vec())
+}
+
+#[derive(Debug, Clone)]
+pub struct IppAttribute {
+    pub group: u8,
+    pub name: String,
+    pub values: Vec<IppValue>,
+}
+
+pub struct IppRequest {
+    operation: u16,
+    request_id: i32,
+    attributes: Vec<IppAttribute>,
+    document: Vec<u8>,
+}
+
+impl IppRequest {
+    /// Starts a request with the operation attributes every IPP request must carry.
+    pub fn new(operation: u16, printer_uri: &str) -> Self {
+        let mut request = IppRequest {
+            operation,
+            request_id: NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed),
+            attributes: Vec::new(),
+            document: Vec::new(),
+        };
+        request.add(tag::OPERATION_ATTRIBUTES, "attributes-charset", tagged(tag::CHARSET, "utf-8"));
+        request.add(tag::OPERATION_ATTRIBUTES, "attributes-natural-language", tagged(tag::NATURAL_LANGUAGE, "en"));
+        request.add(tag::OPERATION_ATTRIBUTES, "printer-uri", tagged(tag::URI, printer_uri));
+        request
+    }
+
+    pub fn add(&mut self, group: u8, name: &str, value: IppValue) -> &mut Self {
+        self.add_values(group, name, vec![value])
+    }
+
+    pub fn add_name(&mut self, group: u8, name: &str, value: &str) -> &mut Self {
+        self.add(group, name, tagged(tag::NAME_WITHOUT_LANGUAGE, value))
+    }
+
+    pub fn add_keyword(&mut self, group: u8, name: &str, value: &str) -> &mut Self {
+        self.add(group, name, tagged(tag::KEYWORD, value))
+    }
+
+    pub fn add_mime_type(&mut self, group: u8, name: &str, value: &str) -> &mut Self {
+        self.add(group, name, tagged(tag::MIME_MEDIA_TYPE, value))
+    }
+
+    pub fn add_keywords(&mut self, group: u8, name: &str, values: &[&str]) -> &mut Self {
+        self.add_values(group, name, values.iter().map(|v| tagged(tag::KEYWORD, v)).collect())
+    }
+
+    fn add_values(&mut self, group: u8, name: &str, values: Vec<IppValue>) -> &mut Self {
+        self.attributes.push(IppAttribute { group, name: name.to_string(), values });
+        self
+    }
+
+    pub fn with_document(&mut self, document: Vec<u8>) -> &mut Self {
+        self.document = document;
+        self
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut out = Vec::with_capacity(256 + self.document.len());
+        out.extend_from_slice(&IPP_VERSION);
+        out.extend_from_slice(&self.operation.to_be_bytes());
+        out.extend_from_slice(&self.request_id.to_be_bytes());
+
+        // Attributes of the same group have to be written under a single delimiter.
+        for group in [tag::OPERATION_ATTRIBUTES, tag::JOB_ATTRIBUTES, tag::PRINTER_ATTRIBUTES] {
+            let mut attributes = self.attributes.iter().filter(|a| a.group == group).peekable();
+            if attributes.peek().is_none() {
+                continue;
+            }
+            out.push(group);
+            for attribute in attributes {
+                for (i, value) in attribute.values.iter().enumerate() {
+                    let (value_tag, bytes) = value.encode();
+                    let name: &[u8] = if i == 0 { attribute.name.as_bytes() } else { &[] };
+                    out.push(value_tag);
+                    out.extend_from_slice(&field_length(name.len(), &attribute.name)?.to_be_bytes());
+                    out.extend_from_slice(name);
+                    out.extend_from_slice(&field_length(bytes.len(), &attribute.name)?.to_be_bytes());
+                    out.extend_from_slice(&bytes);
+                }
+            }
+        }
+        out.push(tag::END_OF_ATTRIBUTES);
+        out.extend_from_slice(&self.document);
+        Ok(out)
+    }
+}
+
+/// Names and values are prefixed with a 16-bit length, anything longer can't be sent.
+fn field_length(len: usize, attribute: &str) -> Result<u16, Box<dyn Error>> {
+    u16::try_from(len).map_err(|_| format!("IPP attribute {} is too long ({} bytes)", attribute, len).into())
+}
+
+#[derive(Debug)]
+pub struct IppResponse {
+    pub status: u16,
+    pub request_id: i32,
+    pub attributes: Vec<IppAttribute>,
+}
+
+impl IppResponse {
+    pub fn is_successful(&self) -> bool {
+        // successful-ok through successful-ok-events-complete
+        self.status < 0x0100
+    }
+
+    pub fn get(&self, group: u8, name: &str) -> Option<&IppAttribute> {
+        self.attributes.iter().find(|a| a.group == group && a.name == name)
+    }
+
+    pub fn get_value(&self, group: u8, name: &str) -> Option<&IppValue> {
+        self.get(group, name).and_then(|a| a.values.first())
+    }
+
+    pub fn status_message(&self) -> String {
+        self.get_value(tag::OPERATION_ATTRIBUTES, "status-message")
+            .and_then(IppValue::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("IPP status 0x{:04x}", self.status))
+    }
+
+    pub fn decode(data: &[u8]) -> Result<IppResponse, Box<dyn Error>> {
+        let mut reader = ByteReader { data, pos: 0 };
+        reader.take(2)?;
+        let status = reader.read_u16()?;
+        let request_id = reader.read_i32()?;
+
+        let mut attributes: Vec<IppAttribute> = Vec::new();
+        let mut group = 0u8;
+        loop {
+            let value_tag = reader.read_u8()?;
+            if value_tag == tag::END_OF_ATTRIBUTES {
+                break;
+            }
+            if value_tag < 0x10 {
+                group = value_tag;
+                continue;
+            }
+            let name_len = reader.read_u16()? as usize;
+            let name = String::from_utf8_lossy(reader.take(name_len)?).into_owned();
+            let value_len = reader.read_u16()? as usize;
+            let value = IppValue::decode(value_tag, reader.take(value_len)?);
+
+            if name.is_empty() {
+                // An additional value of the previous attribute
+                match attributes.last_mut() {
+                    Some(last) => last.values.push(value),
+                    None => return Err("IPP response starts with an unnamed value".into()),
+                }
+            } else {
+                attributes.push(IppAttribute { group, name, values: vec![value] });
+            }
+        }
+
+        Ok(IppResponse { status, request_id, attributes })
+    }
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let end = self.pos + len;
+        if end > self.data.len() {
+            return Err("Truncated IPP response".into());
+        }
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, Box<dyn Error>> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn read_i32(&mut self) -> Result<i32, Box<dyn Error>> {
+        let b = self.take(4)?;
+        Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+/// Where an `ipp://` (or `http://`) printer uri actually lives on the network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IppEndpoint {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl IppEndpoint {
+    pub fn parse(uri: &str) -> Result<IppEndpoint, Box<dyn Error>> {
+        let (scheme, rest) = uri.split_once("://").ok_or_else(|| format!("Invalid printer uri: {}", uri))?;
+        let default_port = match scheme {
+            "ipp" => DEFAULT_PORT,
+            "http" => 80,
+            "ipps" | "https" => return Err(format!("Encrypted printer uris are not supported: {}", uri).into()),
+            _ => return Err(format!("Unsupported printer uri scheme: {}", scheme).into()),
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (host, port.parse().map_err(|_| format!("Invalid port in printer uri: {}", uri))?),
+            _ => (authority, default_port),
+        };
+        // An IPv6 literal is bracketed in the uri but not when connecting
+        let host = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+        if host.is_empty() {
+            return Err(format!("Missing host in printer uri: {}", uri).into());
+        }
+
+        Ok(IppEndpoint { host: host.to_string(), port, path: path.to_string() })
+    }
+
+    /// Host and port as they go into the Host header.
+    fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// Sends an IPP request as an HTTP POST and decodes the IPP response body.
+    pub fn send(&self, request: &IppRequest) -> Result<IppResponse, Box<dyn Error>> {
+        let body = request.encode()?;
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))
+            .map_err(|e| format!("Failed to connect to {}: {}", self.authority(), e))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+        let header = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/ipp\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.authority(),
+            body.len()
+        );
+        stream.write_all(header.as_bytes())?;
+        stream.write_all(&body)?;
+        stream.flush()?;
+
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw)?;
+        let body = parse_http_response(&raw)?;
+        let response = IppResponse::decode(&body)?;
+        if response.request_id != request.request_id {
+            return Err(format!("IPP response for request {} answered request {}", request.request_id, response.request_id).into());
+        }
+        Ok(response)
+    }
+}
+
+fn parse_http_response(raw: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let header_end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("Malformed HTTP response from printer")?;
+    let head = String::from_utf8_lossy(&raw[..header_end]);
+    let body = &raw[header_end + 4..];
+
+    let mut lines = head.lines();
+    let status_line = lines.next().unwrap_or_default();
+    let status_code: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| format!("Malformed HTTP status line: {}", status_line))?;
+    if status_code != 200 {
+        return Err(format!("Printer answered with HTTP {}", status_line).into());
+    }
+
+    let mut chunked = false;
+    let mut content_length = None;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("transfer-encoding") && value.eq_ignore_ascii_case("chunked") {
+                chunked = true;
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<usize>().ok();
+            }
+        }
+    }
+
+    if chunked {
+        return decode_chunked(body);
+    }
+    match content_length {
+        Some(len) if len <= body.len() => Ok(body[..len].to_vec()),
+        Some(_) => Err("Truncated HTTP response from printer".into()),
+        None => Ok(body.to_vec()),
+    }
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut out = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or("Malformed chunked HTTP response")?;
+        let size_line = String::from_utf8_lossy(&body[..line_end]);
+        let size_str = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_str, 16).map_err(|_| format!("Invalid chunk size: {}", size_str))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if body.len() < size + 2 {
+            return Err("Truncated chunked HTTP response".into());
+        }
+        out.extend_from_slice(&body[..size]);
+        body = &body[size + 2..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    /// An IPP response as a printer would send it, built from the same encoder.
+    fn response_bytes(status: u16, request_id: i32, attributes: &[(u8, &str, IppValue)]) -> Vec<u8> {
+        let mut response = IppRequest {
+            operation: status,
+            request_id,
+            attributes: Vec::new(),
+            document: Vec::new(),
+        };
+        for (group, name, value) in attributes {
+            response.add(*group, name, value.clone());
+        }
+        response.encode().unwrap()
+    }
+
+    #[test]
+    fn values_survive_encoding() {
+        for value in [
+            IppValue::Integer(-7),
+            IppValue::Enum(9),
+            IppValue::Boolean(true),
+            IppValue::Text("héllo".to_string()),
+            IppValue::Other(0x30, vec![1, 2, 3]),
+        ] {
+            let (value_tag, bytes) = value.encode();
+            assert_eq!(IppValue::decode(value_tag, &bytes), value);
+        }
+        // Keywords, uris and the like decode as text
+        assert_eq!(IppValue::decode(tag::KEYWORD, b"one-sided"), IppValue::Text("one-sided".to_string()));
+    }
+
+    #[test]
+    fn request_decodes_with_groups_and_multiple_values() {
+        let mut request = IppRequest::new(operation::GET_PRINTER_ATTRIBUTES, "ipp://printer.local/ipp/print");
+        request
+            .add_keywords(tag::OPERATION_ATTRIBUTES, "requested-attributes", &["printer-state", "media-supported"])
+            .add(tag::JOB_ATTRIBUTES, "copies", IppValue::Integer(2));
+        let encoded = request.encode().unwrap();
+        assert_eq!(&encoded[..2], &IPP_VERSION);
+
+        // The header layout is shared with responses, the operation takes the status' place
+        let decoded = IppResponse::decode(&encoded).unwrap();
+        assert_eq!(decoded.status, operation::GET_PRINTER_ATTRIBUTES);
+        assert_eq!(decoded.request_id, request.request_id);
+        assert_eq!(
+            decoded.get_value(tag::OPERATION_ATTRIBUTES, "printer-uri").and_then(IppValue::as_str),
+            Some("ipp://printer.local/ipp/print")
+        );
+        let requested = decoded.get(tag::OPERATION_ATTRIBUTES, "requested-attributes").unwrap();
+        assert_eq!(requested.values.len(), 2);
+        assert_eq!(requested.values[1].as_str(), Some("media-supported"));
+        assert_eq!(decoded.get_value(tag::JOB_ATTRIBUTES, "copies"), Some(&IppValue::Integer(2)));
+        assert!(decoded.get(tag::PRINTER_ATTRIBUTES, "copies").is_none());
+    }
+
+    #[test]
+    fn oversized_value_is_an_error() {
+        let mut request = IppRequest::new(operation::PRINT_JOB, "ipp://printer.local/");
+        request.add_name(tag::OPERATION_ATTRIBUTES, "job-name", &"x".repeat(u16::MAX as usize + 1));
+        assert!(request.encode().is_err());
+    }
+
+    #[test]
+    fn truncated_response_is_an_error() {
+        let encoded = response_bytes(0, 1, &[(tag::JOB_ATTRIBUTES, "job-id", IppValue::Integer(12))]);
+        assert!(IppResponse::decode(&encoded[..encoded.len() - 3]).is_err());
+        assert!(IppResponse::decode(&encoded[..5]).is_err());
+    }
+
+    #[test]
+    fn status_message_falls_back_to_the_code() {
+        let encoded = response_bytes(0x0406, 1, &[]);
+        let response = IppResponse::decode(&encoded).unwrap();
+        assert!(!response.is_successful());
+        assert_eq!(response.status_message(), "IPP status 0x0406");
+    }
+
+    #[test]
+    fn http_response_with_content_length() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Type: application/ipp\r\nContent-Length: 3\r\n\r\nabcdef";
+        assert_eq!(parse_http_response(raw).unwrap(), b"abc");
+        let truncated = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nabc";
+        assert!(parse_http_response(truncated).is_err());
+        let unbounded = b"HTTP/1.0 200 OK\r\n\r\nabc";
+        assert_eq!(parse_http_response(unbounded).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn http_error_status_is_an_error() {
+        let raw = b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+        assert!(parse_http_response(raw).is_err());
+        assert!(parse_http_response(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+
+    #[test]
+    fn chunked_bodies() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4;ext=1\r\nWiki\r\nA\r\npedia in c\r\n0\r\n\r\n";
+        assert_eq!(parse_http_response(raw).unwrap(), b"Wikipedia in c");
+        assert_eq!(decode_chunked(b"0\r\n\r\n").unwrap(), b"");
+        assert!(decode_chunked(b"5\r\nabc").is_err());
+        assert!(decode_chunked(b"zz\r\nabc\r\n").is_err());
+        assert!(decode_chunked(b"3\r\nabc\r\n").is_err());
+    }
+
+    #[test]
+    fn endpoint_from_uri() {
+        let endpoint = IppEndpoint::parse("ipp://printer.local/ipp/print").unwrap();
+        assert_eq!(endpoint, IppEndpoint { host: "printer.local".to_string(), port: DEFAULT_PORT, path: "/ipp/print".to_string() });
+
+        let endpoint = IppEndpoint::parse("http://192.168.1.20:8631").unwrap();
+        assert_eq!((endpoint.host.as_str(), endpoint.port, endpoint.path.as_str()), ("192.168.1.20", 8631, "/"));
+        assert_eq!(IppEndpoint::parse("http://printer.local/ipp").unwrap().port, 80);
+
+        let endpoint = IppEndpoint::parse("ipp://[::1]:631/ipp/print").unwrap();
+        assert_eq!((endpoint.host.as_str(), endpoint.port, endpoint.path.as_str()), ("::1", 631, "/ipp/print"));
+        assert_eq!(endpoint.authority(), "[::1]:631");
+        let endpoint = IppEndpoint::parse("ipp://[fe80::1]/ipp/print").unwrap();
+        assert_eq!((endpoint.host.as_str(), endpoint.port), ("fe80::1", DEFAULT_PORT));
+        assert!(IppEndpoint::parse("ipp://[]/ipp/print").is_err());
+
+        assert!(IppEndpoint::parse("ipps://printer.local/ipp/print").is_err());
+        assert!(IppEndpoint::parse("https://printer.local/ipp/print").is_err());
+        assert!(IppEndpoint::parse("lpd://printer.local/queue").is_err());
+        assert!(IppEndpoint::parse("printer.local").is_err());
+        assert!(IppEndpoint::parse("ipp:///ipp/print").is_err());
+        assert!(IppEndpoint::parse("ipp://printer.local:port/").is_err());
+    }
+
+    #[test]
+    fn round_trip_against_a_stub_printer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let printer = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut raw = Vec::new();
+            let mut buffer = [0u8; 1024];
+            // Read until the whole body announced by Content-Length is in
+            let (header_end, length) = loop {
+                let read = stream.read(&mut buffer).unwrap();
+                assert!(read > 0, "client closed the connection early");
+                raw.extend_from_slice(&buffer[..read]);
+                if let Some(end) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+                    let head = String::from_utf8_lossy(&raw[..end]).to_lowercase();
+                    let length: usize = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .map(|v| v.trim().parse().unwrap())
+                        .unwrap();
+                    if raw.len() >= end + 4 + length {
+                        break (end, length);
+                    }
+                }
+            };
+            let head = String::from_utf8_lossy(&raw[..header_end]).into_owned();
+            assert!(head.starts_with("POST /ipp/print HTTP/1.1"));
+            let body = &raw[header_end + 4..header_end + 4 + length];
+            let request = IppResponse::decode(body).unwrap();
+            assert_eq!(request.status, operation::PRINT_JOB);
+            assert!(body.ends_with(b"%PDF-1.4"));
+
+            let ipp = response_bytes(0, request.request_id, &[
+                (tag::OPERATION_ATTRIBUTES, "status-message", IppValue::Text("successful-ok".to_string())),
+                (tag::JOB_ATTRIBUTES, "job-id", IppValue::Integer(42)),
+                (tag::JOB_ATTRIBUTES, "job-state", IppValue::Enum(3)),
+            ]);
+            // Chunked like CUPS answers, split in two chunks
+            let (first, second) = ipp.split_at(ipp.len() / 2);
+            let mut answer = b"HTTP/1.1 200 OK\r\nContent-Type: application/ipp\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+            for chunk in [first, second] {
+                answer.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+                answer.extend_from_slice(chunk);
+                answer.extend_from_slice(b"\r\n");
+            }
+            answer.extend_from_slice(b"0\r\n\r\n");
+            stream.write_all(&answer).unwrap();
+        });
+
+        let uri = format!("ipp://127.0.0.1:{}/ipp/print", port);
+        let endpoint = IppEndpoint::parse(&uri).unwrap();
+        let mut request = IppRequest::new(operation::PRINT_JOB, &uri);
+        request
+            .add_mime_type(tag::OPERATION_ATTRIBUTES, "document-format", "application/pdf")
+            .with_document(b"%PDF-1.4".to_vec());
+        let response = endpoint.send(&request).unwrap();
+        printer.join().unwrap();
+
+        assert!(response.is_successful());
+        assert_eq!(response.status_message(), "successful-ok");
+        assert_eq!(response.get_value(tag::JOB_ATTRIBUTES, "job-id").and_then(IppValue::as_i32), Some(42));
+        assert_eq!(response.get_value(tag::JOB_ATTRIBUTES, "job-state"), Some(&IppValue::Enum(3)));
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    env,
+    error::Error,
+    path::PathBuf,
+};
+
+use crate::printer::PdfImageInserter;
+use crate::store;
+
+
+/// One page of images waiting to be composed and printed, together with the
+/// layout it was queued with so it can be replayed after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: u64,
+    /// The photos filling the layout's slots, in slot order. Entries written
+    /// before layouts had several slots hold a single `image_path`.
+    #[serde(alias = "image_path", deserialize_with = "one_or_many")]
+    pub image_paths: Vec<String>,
+    pub layout: PdfImageInserter,
+    /// The page still has empty slots and waits for more photos before it's printed.
+    #[serde(default)]
+    pub waiting: bool,
+    pub attempts: u32,
+    /// Set once the printer accepted the job, so a restart only has to keep tracking it.
+    pub job_id: Option<i32>,
+    #[serde(default)]
+    pub printer_name: Option<String>,
+    /// The generated PDF, cleaned up according to the retention policy once printed.
+    #[serde(default)]
+    pub output_path: Option<PathBuf>,
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(path) => vec![path],
+        OneOrMany::Many(paths) => paths,
+    })
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct JournalData {
+    next_id: u64,
+    entries: Vec<JournalEntry>,
+}
+
+/// On-disk list of unfinished print jobs. Every change is written through
+/// immediately, so a crash never loses more than the job being written.
+pub struct Journal {
+    path: PathBuf,
+    data: JournalData,
+}
+
+impl Journal {
+
+    pub fn default_path() -> PathBuf {
+        env::current_dir().unwrap().join("print_journal.json")
+    }
+
+    pub fn open(path: PathBuf) -> Result<Journal, Box<dyn Error>> {
+        let data = store::load(&path, "journal")?;
+        Ok(Journal { path, data })
+    }
+
+    /// For the inserter, which can't wait for anyone to fix the file, see `store::load_or_reset`.
+    pub fn open_or_reset(path: PathBuf) -> Journal {
+        let data = store::load_or_reset(&path, "journal");
+        Journal { path, data }
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.data.entries
+    }
+
+    pub fn get(&self, id: u64) -> Option<&JournalEntry> {
+        self.data.entries.iter().find(|e| e.id == id)
+    }
+
+    pub fn add(&mut self, image_paths: Vec<String>, layout: PdfImageInserter) -> Result<u64, Box<dyn Error>> {
+        self.push(image_paths, layout, false)
+    }
+
+    /// Adds a page that waits for more photos, see `JournalEntry::waiting`.
+    pub fn add_waiting(&mut self, image_paths: Vec<String>, layout: PdfImageInserter) -> Result<u64, Box<dyn Error>> {
+        self.push(image_paths, layout, true)
+    }
+
+    fn push(&mut self, image_paths: Vec<String>, layout: PdfImageInserter, waiting: bool) -> Result<u64, Box<dyn Error>> {
+        let id = self.data.next_id;
+        self.data.next_id += 1;
+        self.data.entries.push(JournalEntry {
+            id,
+            image_paths,
+            layout,
+            waiting,
+            attempts: 0,
+            job_id: None,
+            printer_name: None,
+            output_path: None,
+        });
+        self.save()?;
+        Ok(id)
+    }
+
+    pub fn update(&mut self, id: u64, f: impl FnOnce(&mut JournalEntry)) -> Result<(), Box<dyn Error>> {
+        let entry = self
+            .data
+            .entries
+            .iter_mut()
+            .find(|e| e.id == id)
+            .ok_or_else(|| format!("No journal entry with id {}", id))?;
+        f(entry);
+        self.save()
+    }
+
+    pub fn remove(&mut self, id: u64) -> Result<(), Box<dyn Error>> {
+        self.data.entries.retain(|e| e.id != id);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        store::save(&self.path, "journal", &self.data)
+    }
+}
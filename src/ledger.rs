@@ -0,0 +1,102 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    error::Error,
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::store;
+
+
+/// A photo that was handed to the printer in exactly this version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub image_path: String,
+    /// FNV-1a hash of the file's contents, so a photo rewritten under the same
+    /// name counts as new while one that was only touched doesn't.
+    pub hash: String,
+    /// Seconds since the Unix epoch.
+    pub printed_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LedgerData {
+    entries: Vec<LedgerEntry>,
+}
+
+/// On-disk record of every photo that was printed, so restarting the app or
+/// picking the same folder again doesn't print anything twice.
+pub struct Ledger {
+    path: PathBuf,
+    data: LedgerData,
+}
+
+/// Shared between the inserter thread, which records photos, and the UI, which
+/// can forget them to print them again.
+pub type SharedLedger = Arc<Mutex<Ledger>>;
+
+impl Ledger {
+
+    pub fn default_path() -> PathBuf {
+        env::current_dir().unwrap().join("print_ledger.json")
+    }
+
+    /// Starting over with an empty ledger only risks printing photos twice, see `store::load_or_reset`.
+    pub fn open_or_reset(path: PathBuf) -> Ledger {
+        let data = store::load_or_reset(&path, "ledger");
+        Ledger { path, data }
+    }
+
+    pub fn contains(&self, image_path: &str, hash: &str) -> bool {
+        self.data.entries.iter().any(|e| e.image_path == image_path && e.hash == hash)
+    }
+
+    /// Records the photo as printed. Returns false if this version of it was already.
+    pub fn record(&mut self, image_path: &str, hash: &str) -> Result<bool, Box<dyn Error>> {
+        if self.contains(image_path, hash) {
+            return Ok(false);
+        }
+        let printed_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        self.data.entries.push(LedgerEntry {
+            image_path: image_path.to_string(),
+            hash: hash.to_string(),
+            printed_at,
+        });
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Forgets every version of the photo, so the next time it comes in it gets printed again.
+    pub fn forget(&mut self, image_path: &str) -> Result<(), Box<dyn Error>> {
+        self.data.entries.retain(|e| e.image_path != image_path);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        store::save(&self.path, "ledger", &self.data)
+    }
+}
+
+/// 64-bit FNV-1a over the file's contents, as hex. Not cryptographic, it only
+/// has to tell versions of the same photo apart.
+pub fn content_hash(image_path: &str) -> io::Result<String> {
+    let mut file = File::open(image_path)?;
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        for &byte in &buffer[..read] {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    Ok(format!("{:016x}", hash))
+}
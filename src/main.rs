@@ -3,14 +3,52 @@ mod watcher;
 mod printer;
 mod pdfwrap;
 mod printer_wrapper;
+mod ipp;
+mod journal;
+mod ledger;
+mod profiles;
+mod store;
+mod cli;
+mod headless;
+mod compose;
+mod upload;
+
+use cli::Command;
+use std::{env, process};
+
 fn main() {
     if cfg!(target_os = "macos") {
         println!("Running on MacOS");
+    } else if cfg!(target_os = "linux") {
+        println!("Running on Linux");
     } else {
         println!("Running on Windows");
     }
 
 
+    match cli::parse(env::args().skip(1)) {
+        Ok(Command::Gui) => run_gui(),
+        Ok(Command::Headless(args)) => {
+            if let Err(e) = headless::run(args) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Ok(Command::Compose(args)) => {
+            if let Err(e) = compose::run(args) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Ok(Command::Help) => println!("{}", cli::USAGE),
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+fn run_gui() {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([ui::INITIAL_WIDTH, ui::INITIAL_HEIGHT])
@@ -134,6 +134,34 @@ impl Library {
         unsafe { bindings_pdfium::FPDF_GetPageHeightF(page.handle.as_ptr()) }
     }
 
+    /// The visible part of the page in PDF user space, i.e. the intersection
+    /// of its MediaBox and CropBox, before `/Rotate` is applied.
+    pub fn get_page_bounding_box(&self, page: &PageHandle) -> Option<PageBox> {
+        let mut rect = bindings_pdfium::FS_RECTF {
+            left: 0.0,
+            top: 0.0,
+            right: 0.0,
+            bottom: 0.0,
+        };
+        let ok = unsafe { bindings_pdfium::FPDF_GetPageBoundingBox(page.handle.as_ptr(), &mut rect) };
+        (ok != 0).then_some(PageBox {
+            left: rect.left,
+            bottom: rect.bottom,
+            right: rect.right,
+            top: rect.top,
+        })
+    }
+
+    /// The page's `/Rotate`, which pdfium applies when rendering.
+    pub fn get_page_rotation(&self, page: &PageHandle) -> PageOrientation {
+        match unsafe { bindings_pdfium::FPDFPage_GetRotation(page.handle.as_ptr()) } {
+            1 => PageOrientation::Clockwise,
+            2 => PageOrientation::Flip,
+            3 => PageOrientation::CounterClockwise,
+            _ => PageOrientation::Normal,
+        }
+    }
+
     pub fn render_page_to_bitmap(
         &self,
         bitmap: &mut BitmapHandle,
@@ -358,6 +386,7 @@ impl BitmapFormat {
 }
 
 /// Orientation to render the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PageOrientation {
     /// normal
     Normal = 0,
@@ -369,6 +398,25 @@ pub enum PageOrientation {
     CounterClockwise = 3,
 }
 
+/// A page rectangle in PDF user space, origin at the bottom left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageBox {
+    pub left: f32,
+    pub bottom: f32,
+    pub right: f32,
+    pub top: f32,
+}
+
+impl PageBox {
+    pub fn width(&self) -> f32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> f32 {
+        self.top - self.bottom
+    }
+}
+
 pub mod rendering_flags {
 
 
@@ -1,17 +1,104 @@
-use lopdf::{Document, Object, Dictionary, Stream, Error as LopdfError};
+use lopdf::{Document, Object, ObjectId, Dictionary, Stream, Error as LopdfError};
 use lopdf::content::{Content, Operation};
-use image::{ImageReader, DynamicImage};
+use image::{ImageReader, ImageFormat, ImageDecoder, ImageResult, DynamicImage, Rgba, RgbaImage};
+use image::metadata::Orientation;
+use image::imageops::FilterType;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::{BTreeSet, HashMap, HashSet},
     error::Error,
-    sync::mpsc::{self, Sender, Receiver},
+    io::{BufRead, Cursor, Seek},
+    sync::Arc,
+    sync::mpsc::{self, Sender, Receiver, RecvTimeoutError},
     thread,
     env,
-    path::PathBuf
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 
-use crate::printer_wrapper::{Printer, make_printer};
+use crate::journal::Journal;
+use crate::ledger::{content_hash, SharedLedger};
+use crate::printer_wrapper::{Printer, PrintJob, PrintOptions, JobState, make_printer};
 
+const JOB_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+/// How long a job's printer may fail to answer before the job is given up on.
+const JOB_QUERY_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+pub const DEFAULT_MAX_DPI: u32 = 300;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrintStatus {
+    /// Held back until enough photos arrived to fill every slot of the template.
+    Waiting { remaining: usize },
+    Queued,
+    /// This version of the photo was printed before, see `Ledger`.
+    AlreadyPrinted,
+    /// Tried again until it prints or the operator cancels it.
+    Retrying { attempt: u32, error: String },
+    Job(PrintJob, JobState),
+    Failed(String),
+}
+
+impl PrintStatus {
+    pub fn label(&self) -> String {
+        match self {
+            PrintStatus::Waiting { remaining } => format!("waiting for {} more photo(s)", remaining),
+            PrintStatus::Queued => "queued".to_string(),
+            PrintStatus::AlreadyPrinted => "already printed".to_string(),
+            PrintStatus::Retrying { attempt, error } => format!("retrying (attempt {}): {}", attempt, error),
+            PrintStatus::Job(job, state) => format!("job {}: {}", job.id, state.label()),
+            PrintStatus::Failed(e) => format!("failed: {}", e),
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self, PrintStatus::Failed(_) | PrintStatus::Job(_, JobState::Aborted | JobState::Cancelled))
+    }
+
+    pub fn is_unconfirmed(&self) -> bool {
+        matches!(self, PrintStatus::Job(_, JobState::Unlisted))
+    }
+}
+
+/// Settings the UI can change while the inserter thread is running; they are
+/// read again for every job.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrintSettings {
+    /// `None` prints to the system's default printer.
+    pub printer_name: Option<String>,
+    pub options: PrintOptions,
+}
+
+pub type SharedPrintSettings = Arc<Mutex<PrintSettings>>;
+
+/// The layout the inserter thread composes new pages with. The UI can change
+/// it while the thread runs, pages already queued keep the layout they were
+/// journaled with.
+pub type SharedLayout = Arc<Mutex<PdfImageInserter>>;
+
+/// Photos whose retrying job the operator wants to give up on. The inserter
+/// thread picks them up and clears the set.
+pub type CancelRequests = Arc<Mutex<HashSet<String>>>;
+
+/// Sent back from the inserter thread every time an image's print status changes.
+#[derive(Debug, Clone)]
+pub struct PrintUpdate {
+    pub image_path: String,
+    pub status: PrintStatus,
+}
+
+
+fn retry_delay(attempts: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(RETRY_MAX_DELAY)
+}
 
 
 fn get_or_create_xobject(
@@ -24,60 +111,558 @@ fn get_or_create_xobject(
     xobj.as_dict_mut()
 }
 
+/// Draws `content` on top of whatever the page already shows. The existing
+/// content is wrapped in q/Q, so a transform it leaves behind can't move or
+/// turn the photos away from their slots.
+fn append_content(doc: &mut Document, page_id: ObjectId, mut content: Content) -> Result<(), LopdfError> {
+    let save_id = doc.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+    content.operations.insert(0, Operation::new("Q", vec![]));
+    let content_id = doc.add_object(Stream::new(Dictionary::new(), content.encode()?));
 
+    let page_dict = doc.get_dictionary_mut(page_id)?;
+    let existing = page_dict.get(b"Contents")?;
+    let new_obj = match existing {
+        Object::Reference(rid) => Object::Array(vec![
+            Object::Reference(save_id),
+            Object::Reference(*rid),
+            Object::Reference(content_id),
+        ]),
+        Object::Array(arr) => {
+            let mut arr = arr.clone();
+            arr.insert(0, Object::Reference(save_id));
+            arr.push(Object::Reference(content_id));
+            Object::Array(arr)
+        }
+        _ => Object::Array(vec![Object::Reference(save_id), Object::Reference(content_id)]),
+    };
+    page_dict.set("Contents", new_obj);
+    Ok(())
+}
+
+
+
+
+/// What happens to a generated PDF once its job printed successfully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Retention {
+    Keep,
+    #[default]
+    Archive,
+    Delete,
+}
+
+impl Retention {
+    pub const ALL: [Retention; 3] = [Retention::Keep, Retention::Archive, Retention::Delete];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Retention::Keep => "Keep",
+            Retention::Archive => "Archive",
+            Retention::Delete => "Delete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputSettings {
+    pub dir: PathBuf,
+    pub retention: Retention,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        OutputSettings {
+            dir: env::current_dir().unwrap().join("print_output"),
+            retention: Retention::default(),
+        }
+    }
+}
+
+impl OutputSettings {
+    pub fn archive_dir(&self) -> PathBuf {
+        self.dir.join("archive")
+    }
 
+    pub fn apply_retention(&self, pdf_path: &Path) -> Result<(), Box<dyn Error>> {
+        match self.retention {
+            Retention::Keep => {}
+            Retention::Archive => {
+                let archive_dir = self.archive_dir();
+                fs::create_dir_all(&archive_dir)
+                    .map_err(|e| format!("Failed to create archive folder {:?}: {}", archive_dir, e))?;
+                let file_name = pdf_path.file_name().ok_or_else(|| format!("Not a file: {:?}", pdf_path))?;
+                fs::rename(pdf_path, archive_dir.join(file_name))
+                    .map_err(|e| format!("Failed to archive {:?}: {}", pdf_path, e))?;
+            }
+            Retention::Delete => {
+                fs::remove_file(pdf_path).map_err(|e| format!("Failed to delete {:?}: {}", pdf_path, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// Color space the photo is embedded with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ImageColor {
+    #[default]
+    Grayscale,
+    Rgb,
+    Cmyk,
+}
+
+impl ImageColor {
+    pub const ALL: [ImageColor; 3] = [ImageColor::Grayscale, ImageColor::Rgb, ImageColor::Cmyk];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImageColor::Grayscale => "Grayscale",
+            ImageColor::Rgb => "Color (RGB)",
+            ImageColor::Cmyk => "Color (CMYK)",
+        }
+    }
+
+    /// Number of components a JPEG needs to be embedded as-is in this color space.
+    /// Adobe CMYK JPEGs are often stored inverted, so those are always re-encoded.
+    fn jpeg_components(&self) -> Option<u8> {
+        match self {
+            ImageColor::Grayscale => Some(1),
+            ImageColor::Rgb => Some(3),
+            ImageColor::Cmyk => None,
+        }
+    }
+
+    fn pdf_color_space(&self) -> &'static [u8] {
+        match self {
+            ImageColor::Grayscale => b"DeviceGray",
+            ImageColor::Rgb => b"DeviceRGB",
+            ImageColor::Cmyk => b"DeviceCMYK",
+        }
+    }
+
+    /// Raw 8-bit samples in this color space.
+    fn encode(&self, img: &DynamicImage) -> Vec<u8> {
+        let img = flatten_alpha(img);
+        match self {
+            ImageColor::Grayscale => img.grayscale().into_luma8().into_raw(),
+            ImageColor::Rgb => img.into_rgb8().into_raw(),
+            ImageColor::Cmyk => img
+                .into_rgb8()
+                .pixels()
+                .flat_map(|p| rgb_to_cmyk(p.0))
+                .collect(),
+        }
+    }
+
+    /// How the photo will look once embedded, for the UI preview.
+    pub fn preview(&self, img: &DynamicImage) -> DynamicImage {
+        let img = flatten_alpha(img);
+        match self {
+            ImageColor::Grayscale => img.grayscale(),
+            // The naive CMYK conversion round-trips to the same RGB values
+            ImageColor::Rgb | ImageColor::Cmyk => img,
+        }
+    }
+}
+
+fn rgb_to_cmyk([r, g, b]: [u8; 3]) -> [u8; 4] {
+    let k = 255 - r.max(g).max(b);
+    if k == 255 {
+        return [0, 0, 0, 255];
+    }
+    let scale = |v: u8| (((255 - v - k) as u32 * 255) / (255 - k) as u32) as u8;
+    [scale(r), scale(g), scale(b), k]
+}
+
+/// Transparent areas print as paper, so composite onto white before dropping alpha.
+fn flatten_alpha(img: &DynamicImage) -> DynamicImage {
+    if !img.color().has_alpha() {
+        return img.clone();
+    }
+    let mut flat = RgbaImage::from_pixel(img.width(), img.height(), Rgba([255, 255, 255, 255]));
+    image::imageops::overlay(&mut flat, &img.to_rgba8(), 0, 0);
+    DynamicImage::ImageRgba8(flat)
+}
+
+/// Decodes a photo the right way up according to its EXIF orientation. The flag
+/// tells whether the pixels had to be transformed for that.
+pub fn decode_upright<R: BufRead + Seek>(reader: ImageReader<R>) -> ImageResult<(DynamicImage, bool)> {
+    let mut decoder = reader.into_decoder()?;
+    // A broken EXIF block shouldn't keep the photo from printing
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok((img, orientation != Orientation::NoTransforms))
+}
+
+/// Component count from the first SOF marker of a JPEG file.
+fn jpeg_components(data: &[u8]) -> Option<u8> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        // Fill bytes and markers without a length field
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            pos += 2;
+            continue;
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            return data.get(pos + 9).copied();
+        }
+        pos += 2 + len;
+    }
+    None
+}
 
+
+/// How a photo is sized into a slot whose aspect ratio differs from its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FitMode {
+    /// Whole photo visible, the rest of the slot stays blank.
+    Contain,
+    /// Slot filled completely, the photo is cropped around its center.
+    Cover,
+    /// Slot filled completely, the photo is cropped around `Slot::focus`.
+    Focus,
+    #[default]
+    Stretch,
+}
+
+impl FitMode {
+    pub const ALL: [FitMode; 4] = [FitMode::Contain, FitMode::Cover, FitMode::Focus, FitMode::Stretch];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FitMode::Contain => "Contain",
+            FitMode::Cover => "Cover (center crop)",
+            FitMode::Focus => "Cover (focal point)",
+            FitMode::Stretch => "Stretch",
+        }
+    }
+}
+
+/// A box a photo is placed in, in PDF points from the page's bottom-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Slot {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    #[serde(default)]
+    pub fit: FitMode,
+    /// Point of the photo kept in view by `FitMode::Focus`, as fractions of its
+    /// width and height from the top-left corner.
+    #[serde(default = "default_focus")]
+    pub focus: (f32, f32),
+    /// Counterclockwise, in degrees.
+    #[serde(default)]
+    pub rotation: f32,
+    /// Zero-based index of the template page the slot is on.
+    #[serde(default)]
+    pub page: usize,
+}
+
+fn default_focus() -> (f32, f32) {
+    (0.5, 0.5)
+}
+
+impl Slot {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Slot {
+            x,
+            y,
+            width,
+            height,
+            fit: FitMode::default(),
+            focus: default_focus(),
+            rotation: 0.0,
+            page: 0,
+        }
+    }
+
+    /// The PDF `cm` matrix drawing a photo with the given aspect ratio (width /
+    /// height) into the slot. With the cover modes or a tilted photo it
+    /// overhangs the slot and has to be clipped to it.
+    pub fn image_matrix(&self, aspect: f32) -> [f32; 6] {
+        let (cx, cy, w, h) = self.placement(aspect);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (a, b, c, d) = (w * cos, w * sin, -h * sin, h * cos);
+        [a, b, c, d, cx - (a + c) / 2.0, cy - (b + d) / 2.0]
+    }
+
+    /// Size the photo is drawn at before rotation.
+    pub fn image_size(&self, aspect: f32) -> (f32, f32) {
+        let (_, _, w, h) = self.placement(aspect);
+        (w, h)
+    }
+
+    /// Center and unrotated size of the photo. It is fitted by the bounding box
+    /// of the rotated photo, so arbitrary angles always stay fully visible with
+    /// `Contain`.
+    fn placement(&self, aspect: f32) -> (f32, f32, f32, f32) {
+        let fit = if aspect.is_finite() && aspect > 0.0 { self.fit } else { FitMode::Stretch };
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (s, c) = (sin.abs(), cos.abs());
+
+        let (bx, by, bw, bh) = match fit {
+            FitMode::Stretch => (self.x, self.y, self.width, self.height),
+            _ => {
+                let (fx, fy) = match fit {
+                    FitMode::Focus => {
+                        // Turn the focus with the photo; it is measured from the top, PDF y grows upwards
+                        let (dx, dy) = (self.focus.0 - 0.5, 0.5 - self.focus.1);
+                        ((0.5 + dx * cos - dy * sin).clamp(0.0, 1.0), (0.5 - dx * sin - dy * cos).clamp(0.0, 1.0))
+                    }
+                    _ => (0.5, 0.5),
+                };
+                let bbox_aspect = (aspect * c + s) / (aspect * s + c);
+                let width = if fit == FitMode::Contain {
+                    self.width.min(self.height * bbox_aspect)
+                } else {
+                    self.width.max(self.height * bbox_aspect)
+                };
+                let height = width / bbox_aspect;
+                (self.x + (self.width - width) * fx, self.y + (self.height - height) * (1.0 - fy), width, height)
+            }
+        };
+
+        let (w, h) = match fit {
+            FitMode::Stretch if s > c => (bh, bw),
+            FitMode::Stretch => (bw, bh),
+            _ => {
+                let k = bw / (aspect * c + s);
+                (aspect * k, k)
+            }
+        };
+        (bx + bw / 2.0, by + bh / 2.0, w, h)
+    }
+}
+
+/// How incoming photos are spread over the slots of a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SlotFill {
+    /// Every slot gets the next photo, a page is printed once all are filled.
+    #[default]
+    Successive,
+    /// Every photo is printed on its own page, repeated in all slots.
+    Repeat,
+}
+
+impl SlotFill {
+    pub const ALL: [SlotFill; 2] = [SlotFill::Successive, SlotFill::Repeat];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SlotFill::Successive => "One photo per slot",
+            SlotFill::Repeat => "Same photo in every slot",
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoredLayout")]
 pub struct PdfImageInserter {
     template_path: String,
-    x: f32,
-    y: f32,
-    width: f32,
-    height: f32,
+    slots: Vec<Slot>,
+    fill: SlotFill,
+    output: OutputSettings,
+    image_color: ImageColor,
+    /// Photos with more pixels than this resolution needs for the placement box are scaled down.
+    max_dpi: Option<u32>,
+}
+
+fn default_max_dpi() -> Option<u32> {
+    Some(DEFAULT_MAX_DPI)
+}
+
+/// A layout as read from a journal, profile or config file. Before templates
+/// had several slots a layout had a single box given by x/y/width/height.
+#[derive(Deserialize)]
+struct StoredLayout {
+    template_path: String,
+    #[serde(default)]
+    slots: Vec<Slot>,
+    x: Option<f32>,
+    y: Option<f32>,
+    width: Option<f32>,
+    height: Option<f32>,
+    #[serde(default)]
+    fill: SlotFill,
+    #[serde(default)]
+    output: OutputSettings,
+    #[serde(default)]
+    image_color: ImageColor,
+    #[serde(default = "default_max_dpi")]
+    max_dpi: Option<u32>,
+}
+
+impl From<StoredLayout> for PdfImageInserter {
+    fn from(stored: StoredLayout) -> Self {
+        let mut slots = stored.slots;
+        if let (true, Some(x), Some(y), Some(width), Some(height)) = (slots.is_empty(), stored.x, stored.y, stored.width, stored.height) {
+            slots.push(Slot::new(x, y, width, height));
+        }
+        PdfImageInserter {
+            template_path: stored.template_path,
+            slots,
+            fill: stored.fill,
+            output: stored.output,
+            image_color: stored.image_color,
+            max_dpi: stored.max_dpi,
+        }
+    }
 }
 
 impl PdfImageInserter {
 
-    pub fn save_pdf_path(&self) -> PathBuf {
-        env::current_dir().unwrap().join("print_doc.pdf")
+    pub fn new(template_path: String, slots: Vec<Slot>) -> Self {
+        PdfImageInserter {
+            template_path,
+            slots,
+            fill: SlotFill::default(),
+            output: OutputSettings::default(),
+            image_color: ImageColor::default(),
+            max_dpi: default_max_dpi(),
+        }
     }
 
+    pub fn with_fill(mut self, fill: SlotFill) -> Self {
+        self.fill = fill;
+        self
+    }
 
-    pub fn new_and_spawn(template_path: String, x: f32, y: f32, width: f32, height: f32) -> Result<Sender<String>, Box<dyn Error>> {
-            let (tx, rx) : (Sender<String>, Receiver<String>) = mpsc::channel();
-            // let inserter = PdfImageInserter::new(template_path, x, y, width, height)?;
-            let printer_name = Some("EPSON ET-M1120 Series".to_string());
-            thread::spawn(move || { 
+    pub fn with_output(mut self, output: OutputSettings) -> Self {
+        self.output = output;
+        self
+    }
 
-                let inserter = PdfImageInserter {
-                    template_path: template_path.clone(),
-                    x,
-                    y,
-                    width,
-                    height,
-                };
+    pub fn with_image_color(mut self, image_color: ImageColor) -> Self {
+        self.image_color = image_color;
+        self
+    }
 
-                let printer: Box<dyn Printer> = match make_printer(printer_name.as_deref()) {
-                    Ok(p) => p,
-                    Err(e) => {
-                        eprintln!("Failed to initialize printer: {}", e);
-                        return;
-                    }
-                };
+    pub fn with_max_dpi(mut self, max_dpi: Option<u32>) -> Self {
+        self.max_dpi = max_dpi;
+        self
+    }
 
-                for img in rx {
-                        let save_pdf_path = inserter.save_pdf_path();
+    /// Every job gets its own file, named after the source image, so the
+    /// spooler can still be reading one while the next is generated.
+    pub fn save_pdf_path(&self, image_path: &str) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(&self.output.dir)
+            .map_err(|e| format!("Failed to create output folder {:?}: {}", self.output.dir, e))?;
 
-                        if let Err(insert_err) = inserter.insert_image(&img, &save_pdf_path) {
-                            eprintln!("Insert error: {}", insert_err);
-                            continue;
-                        }
+        let stem = Path::new(image_path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "print_doc".to_string());
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+
+        let mut path = self.output.dir.join(format!("{}_{}.pdf", stem, timestamp));
+        let mut n = 1;
+        while path.exists() {
+            path = self.output.dir.join(format!("{}_{}_{}.pdf", stem, timestamp, n));
+            n += 1;
+        }
+        Ok(path)
+    }
+
+    pub fn output(&self) -> &OutputSettings {
+        &self.output
+    }
+
+
+    pub fn template_path(&self) -> &str {
+        &self.template_path
+    }
+
+    pub fn slots(&self) -> &[Slot] {
+        &self.slots
+    }
+
+    pub fn fill(&self) -> SlotFill {
+        self.fill
+    }
+
+    /// How many incoming photos make up one printed page.
+    pub fn images_per_page(&self) -> usize {
+        match self.fill {
+            SlotFill::Successive => self.slots.len().max(1),
+            SlotFill::Repeat => 1,
+        }
+    }
+
+    /// The photo each slot is filled with; slots past the end of a short batch stay empty.
+    fn slot_images<'a>(&'a self, image_paths: &'a [String]) -> Vec<(&'a Slot, &'a str)> {
+        match self.fill {
+            SlotFill::Successive => self.slots.iter().zip(image_paths.iter().map(String::as_str)).collect(),
+            SlotFill::Repeat => match image_paths.first() {
+                Some(path) => self.slots.iter().map(|slot| (slot, path.as_str())).collect(),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    pub fn image_color(&self) -> ImageColor {
+        self.image_color
+    }
+
+    pub fn max_dpi(&self) -> Option<u32> {
+        self.max_dpi
+    }
 
-                        if let Err(e) = printer.print(&save_pdf_path) {
-                            eprintln!("Print error for {:?}: {}", save_pdf_path, e);
+
+    /// Starts the inserter thread. Photos already in `ledger` or still on their
+    /// way to the printer are skipped, the ledger records them once their job completes.
+    pub fn spawn(
+        layout: SharedLayout,
+        settings: SharedPrintSettings,
+        ledger: SharedLedger,
+        cancel_requests: CancelRequests,
+        status_tx: Sender<PrintUpdate>,
+    ) -> Result<Sender<String>, Box<dyn Error>> {
+            if layout.lock().slots.is_empty() {
+                return Err("The layout has no photo slots".into());
+            }
+            let (tx, rx) : (Sender<String>, Receiver<String>) = mpsc::channel();
+            thread::spawn(move || { 
+
+                let mut queue = PrintQueue::open(settings, ledger, cancel_requests, status_tx);
+                loop {
+                    match rx.recv_timeout(queue.next_wakeup()) {
+                        Ok(img) if !queue.claim(&img) => {}
+                        Ok(img) => {
+                            let inserter = layout.lock().clone();
+                            queue.add_to_batch(img, inserter);
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => {
+                            // Nothing more is coming, print the partly filled page
+                            let inserter = layout.lock().clone();
+                            queue.flush_batch(inserter);
+                            if queue.is_idle() {
+                                break;
+                            }
+                            thread::sleep(queue.next_wakeup());
                         }
-                        println!("Successfully sumbited a print job!");
-                            // std::fs::remove_file(save_pdf_path).unwrap();
                     }
+
+                    queue.cancel_requested();
+                    queue.process_due();
+                    queue.poll_jobs();
+                }
             });
             Ok(tx)
         }
@@ -85,86 +670,685 @@ impl PdfImageInserter {
 
     
 
-    fn insert_image(&self, image_path: &str, output_path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    pub fn insert_image(&self, image_paths: &[String], output_path: &PathBuf) -> Result<(), Box<dyn Error>> {
 
         let mut doc = Document::load(&self.template_path).map_err(|e| format!("Failed to load PDF template: {}", e))?;
 
-        let img = ImageReader::open(image_path).map_err(|e| format!("Failed to open image {}: {}", image_path, e))?
-        .decode().map_err(|e| format!("Failed to decode image {}: {}", image_path, e))?;
-        let gray_dynamic: DynamicImage = img.grayscale();
-        let gray_buf = gray_dynamic.to_luma8();
-        let (w_px, h_px) = gray_buf.dimensions();
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        if pages.is_empty() {
+            return Err("PDF has no pages".into());
+        }
+
+        let slot_images = self.slot_images(image_paths);
+        if let Some((slot, _)) = slot_images.iter().find(|(slot, _)| slot.page >= pages.len()) {
+            return Err(format!("A slot targets page {} but the template has only {} page(s)", slot.page + 1, pages.len()).into());
+        }
+
+        // A photo shown in several slots is embedded once, at the resolution the largest of them needs
+        let mut xobjects: HashMap<&str, (Vec<u8>, ObjectId, f32)> = HashMap::new();
+        for &(_, image_path) in &slot_images {
+            if xobjects.contains_key(image_path) {
+                continue;
+            }
+            let slots: Vec<&Slot> = slot_images
+                .iter()
+                .filter(|(_, path)| *path == image_path)
+                .map(|(slot, _)| *slot)
+                .collect();
+            let (img_stream, aspect) = self.image_stream(image_path, &slots)?;
+            let img_obj_id = doc.add_object(img_stream);
+            let name = format!("Im{}", xobjects.len() + 1).into_bytes();
+            xobjects.insert(image_path, (name, img_obj_id, aspect));
+        }
+
+        let target_pages: BTreeSet<usize> = slot_images.iter().map(|(slot, _)| slot.page).collect();
+        for page in target_pages {
+            let page_id = pages[page];
+            let mut content = Content { operations: vec![] };
+            for (slot, image_path) in slot_images.iter().filter(|(slot, _)| slot.page == page) {
+                let (name, img_obj_id, aspect) = &xobjects[image_path];
+                {
+                    let page_dict = doc.get_dictionary_mut(page_id)?;
+                    let res_obj = page_dict.get_mut(b"Resources")?;
+                    let resources_dict = res_obj
+                        .as_dict_mut()
+                        .map_err(|_| LopdfError::Type)?;
+                    let xobj_dict = get_or_create_xobject(resources_dict)?;
+                    xobj_dict.set(name.clone(), Object::Reference(*img_obj_id));
+                }
+
+                content.operations.push(Operation::new("q", vec![]));
+                // Clip to the slot so cropped photos don't spill over the template
+                content.operations.push(Operation::new(
+                    "re",
+                    vec![slot.x.into(), slot.y.into(), slot.width.into(), slot.height.into()],
+                ));
+                content.operations.push(Operation::new("W", vec![]));
+                content.operations.push(Operation::new("n", vec![]));
+                content.operations.push(Operation::new(
+                    "cm",
+                    slot.image_matrix(*aspect).into_iter().map(Object::Real).collect(),
+                ));
+                content
+                    .operations
+                    .push(Operation::new("Do", vec![Object::Name(name.clone())]));
+                content.operations.push(Operation::new("Q", vec![]));
+            }
+
+            append_content(&mut doc, page_id, content)?;
+        }
+
+        doc.save(&output_path).map_err(|e| format!("Failed to save PDF to {:?}: {}", output_path, e))?;
+        Ok(())
+    }
+
+    /// Builds the image XObject for a photo shown in `slots`, along with its
+    /// aspect ratio. JPEGs that need no conversion are embedded untouched,
+    /// everything else is re-encoded and Flate compressed.
+    fn image_stream(&self, image_path: &str, slots: &[&Slot]) -> Result<(Stream, f32), Box<dyn Error>> {
+        let data = fs::read(image_path).map_err(|e| format!("Failed to open image {}: {}", image_path, e))?;
+        let reader = ImageReader::new(Cursor::new(&data))
+            .with_guessed_format()
+            .map_err(|e| format!("Failed to open image {}: {}", image_path, e))?;
+        let format = reader.format();
+        let (img, reoriented) = decode_upright(reader).map_err(|e| format!("Failed to decode image {}: {}", image_path, e))?;
+
+        let aspect = img.width() as f32 / img.height() as f32;
+        let box_size = slots.iter().fold((0.0f32, 0.0f32), |(w, h), slot| {
+            let (width, height) = slot.image_size(aspect);
+            (w.max(width.abs()), h.max(height.abs()))
+        });
+        let downsampled = self.downsample(&img, box_size);
+        let passthrough = downsampled.is_none()
+            && !reoriented
+            && format == Some(ImageFormat::Jpeg)
+            && self.image_color.jpeg_components().is_some()
+            && jpeg_components(&data) == self.image_color.jpeg_components();
+        let img = downsampled.unwrap_or(img);
 
         let mut img_dict = Dictionary::new();
         img_dict.set("Type", Object::Name(b"XObject".to_vec()));
         img_dict.set("Subtype", Object::Name(b"Image".to_vec()));
-        img_dict.set("Width", Object::Integer(w_px as i64));
-        img_dict.set("Height", Object::Integer(h_px as i64));
-        img_dict.set("ColorSpace", Object::Name(b"DeviceGray".to_vec()));
+        img_dict.set("Width", Object::Integer(img.width() as i64));
+        img_dict.set("Height", Object::Integer(img.height() as i64));
+        img_dict.set("ColorSpace", Object::Name(self.image_color.pdf_color_space().to_vec()));
         img_dict.set("BitsPerComponent", Object::Integer(8));
 
-        let img_stream = Stream::new(img_dict, gray_buf.into_raw());
-        let img_obj_id = doc.add_object(img_stream);
-
-        let pages = doc.get_pages();
-        if pages.is_empty() {
-            return Err("PDF has no pages".into());
+        if passthrough {
+            img_dict.set("Filter", Object::Name(b"DCTDecode".to_vec()));
+            return Ok((Stream::new(img_dict, data), aspect));
         }
-        let (&_, &page_id) = pages.iter().next().unwrap();
-
-        {
-            let page_dict = doc.get_dictionary_mut(page_id)?;
-            let res_obj = page_dict.get_mut(b"Resources")?;
-            let resources_dict = res_obj
-                .as_dict_mut()
-                .map_err(|_| LopdfError::Type)?;
-            let xobj_dict = get_or_create_xobject(resources_dict)?;
-            xobj_dict.set(b"Im1".to_vec(), Object::Reference(img_obj_id));
-        }
-
-        let mut content = Content { operations: vec![] };
-        content.operations.push(Operation::new("q", vec![]));
-        content.operations.push(Operation::new(
-            "cm",
-            vec![
-                self.width.into(),
-                0.into(),
-                0.into(),
-                self.height.into(),
-                self.x.into(),
-                self.y.into(),
-            ],
-        ));
-        content
-            .operations
-            .push(Operation::new("Do", vec![Object::Name(b"Im1".to_vec())]));
-        content.operations.push(Operation::new("Q", vec![]));
-
-        let content_stream = Stream::new(Dictionary::new(), content.encode()?);
-        let content_id = doc.add_object(content_stream);
-
-        {
-            let page_dict = doc.get_dictionary_mut(page_id)?;
-            let existing = page_dict.get(b"Contents")?;
-            let new_obj = match existing {
-                Object::Reference(rid) => Object::Array(vec![
-                    Object::Reference(*rid),
-                    Object::Reference(content_id),
-                ]),
-                Object::Array(arr) => {
-                    let mut arr = arr.clone();
-                    arr.push(Object::Reference(content_id));
-                    Object::Array(arr)
-                }
-                _ => Object::Reference(content_id),
-            };
-            page_dict.set("Contents", new_obj);
+
+        let mut stream = Stream::new(img_dict, self.image_color.encode(&img));
+        stream.compress().map_err(|e| format!("Failed to compress image: {}", e))?;
+        Ok((stream, aspect))
+    }
+
+    /// Scales the photo down to `max_dpi` at the given box size, or `None`
+    /// when it already has no more pixels than that.
+    fn downsample(&self, img: &DynamicImage, (box_w, box_h): (f32, f32)) -> Option<DynamicImage> {
+        let dpi = self.max_dpi? as f32;
+        let target_w = box_w / 72.0 * dpi;
+        let target_h = box_h / 72.0 * dpi;
+        // Keep the aspect ratio and enough pixels for both sides of the box
+        let scale = (target_w / img.width() as f32).max(target_h / img.height() as f32);
+        if scale >= 1.0 || !scale.is_finite() {
+            return None;
         }
-        
-        doc.save(&output_path).map_err(|e| format!("Failed to save PDF to {:?}: {}", output_path, e))?;
-        Ok(())
+        let w = ((img.width() as f32 * scale).round() as u32).max(1);
+        let h = ((img.height() as f32 * scale).round() as u32).max(1);
+        Some(img.resize_exact(w, h, FilterType::Lanczos3))
     }
     
 
     
 }
+
+
+struct ActiveJob {
+    id: u64,
+    printer_name: Option<String>,
+    job: PrintJob,
+    state: JobState,
+    /// Since when querying the job's state fails.
+    unreachable_since: Option<Instant>,
+}
+
+/// Journal-backed work list of the inserter thread: images waiting for a
+/// (re)try and jobs the printer accepted but hasn't finished yet.
+struct PrintQueue {
+    settings: SharedPrintSettings,
+    ledger: SharedLedger,
+    cancel_requests: CancelRequests,
+    /// Content hashes of the claimed photos that haven't printed yet, by path.
+    in_flight: HashMap<String, String>,
+    printers: HashMap<Option<String>, Box<dyn Printer>>,
+    journal: Journal,
+    status_tx: Sender<PrintUpdate>,
+    /// Photos held back until the page has one for every slot.
+    batch: Vec<String>,
+    /// The batch's journal entry, it's journaled like a job that wasn't sent yet.
+    batch_id: Option<u64>,
+    retry_queue: Vec<(u64, Instant)>,
+    active_jobs: Vec<ActiveJob>,
+}
+
+impl PrintQueue {
+    fn open(settings: SharedPrintSettings, ledger: SharedLedger, cancel_requests: CancelRequests, status_tx: Sender<PrintUpdate>) -> Self {
+        let journal_path = Journal::default_path();
+        let journal = Journal::open_or_reset(journal_path.clone());
+        let mut queue = PrintQueue {
+            settings,
+            ledger,
+            cancel_requests,
+            in_flight: HashMap::new(),
+            printers: HashMap::new(),
+            journal,
+            status_tx,
+            batch: Vec::new(),
+            batch_id: None,
+            retry_queue: Vec::new(),
+            active_jobs: Vec::new(),
+        };
+        if queue.journal.entries().is_empty() {
+            return queue;
+        }
+
+        println!("Resuming {} unfinished print jobs from {:?}", queue.journal.entries().len(), journal_path);
+        let now = Instant::now();
+        for entry in queue.journal.entries().to_vec() {
+            for image_path in &entry.image_paths {
+                queue.claim(image_path);
+            }
+            if entry.waiting && queue.batch_id.is_none() {
+                let remaining = entry.layout.images_per_page().saturating_sub(entry.image_paths.len());
+                queue.send_status(&entry.image_paths, PrintStatus::Waiting { remaining });
+                queue.batch = entry.image_paths;
+                queue.batch_id = Some(entry.id);
+                continue;
+            }
+            match entry.job_id {
+                Some(id) => {
+                    let job = PrintJob { id };
+                    queue.send_status(&entry.image_paths, PrintStatus::Job(job, JobState::Pending));
+                    queue.active_jobs.push(ActiveJob {
+                        id: entry.id,
+                        printer_name: entry.printer_name,
+                        job,
+                        state: JobState::Pending,
+                        unreachable_since: None,
+                    });
+                }
+                None => {
+                    queue.send_status(&entry.image_paths, PrintStatus::Queued);
+                    queue.retry_queue.push((entry.id, now));
+                }
+            }
+        }
+        queue
+    }
+
+    /// Printers are opened on first use and kept, so jobs sent to a printer
+    /// the operator switched away from can still be tracked.
+    fn printer(&mut self, name: Option<&str>) -> Result<&dyn Printer, Box<dyn Error>> {
+        let key = name.map(str::to_string);
+        if !self.printers.contains_key(&key) {
+            let printer = make_printer(name).map_err(|e| format!("Failed to initialize printer: {}", e))?;
+            self.printers.insert(key.clone(), printer);
+        }
+        Ok(self.printers[&key].as_ref())
+    }
+
+    fn send_status(&self, image_paths: &[String], status: PrintStatus) {
+        for image_path in image_paths {
+            // The UI may already be gone, nothing to report to then
+            let _ = self.status_tx.send(PrintUpdate { image_path: image_path.clone(), status: status.clone() });
+        }
+    }
+
+    /// Takes the photo on unless it was printed or is being printed already. A
+    /// photo that can't be hashed is let through, inserting it reports the actual error.
+    /// One that is still on its way keeps the status it has.
+    fn claim(&mut self, image_path: &str) -> bool {
+        let hash = match content_hash(image_path) {
+            Ok(hash) => hash,
+            Err(e) => {
+                eprintln!("Failed to hash {}: {}", image_path, e);
+                return true;
+            }
+        };
+        if self.in_flight.get(image_path) == Some(&hash) {
+            println!("{} is being printed already", image_path);
+            return false;
+        }
+        if self.ledger.lock().contains(image_path, &hash) {
+            self.send_status(&[image_path.to_string()], PrintStatus::AlreadyPrinted);
+            return false;
+        }
+        self.in_flight.insert(image_path.to_string(), hash);
+        true
+    }
+
+    /// Records the photos in the ledger now that they're on paper.
+    fn printed(&mut self, image_paths: &[String]) {
+        for image_path in image_paths {
+            let Some(hash) = self.in_flight.remove(image_path) else { continue };
+            if let Err(e) = self.ledger.lock().record(image_path, &hash) {
+                eprintln!("Failed to update print ledger: {}", e);
+            }
+        }
+    }
+
+    /// Lets the photos be claimed again after their job didn't print.
+    fn release(&mut self, image_paths: &[String]) {
+        for image_path in image_paths {
+            self.in_flight.remove(image_path);
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.retry_queue.is_empty() && self.active_jobs.is_empty()
+    }
+
+    fn next_wakeup(&self) -> Duration {
+        let now = Instant::now();
+        self.retry_queue
+            .iter()
+            .map(|(_, due)| due.saturating_duration_since(now))
+            .fold(JOB_POLL_INTERVAL, Duration::min)
+    }
+
+    fn enqueue(&mut self, image_paths: Vec<String>, layout: PdfImageInserter) {
+        self.send_status(&image_paths, PrintStatus::Queued);
+        match self.journal.add(image_paths.clone(), layout.clone()) {
+            Ok(id) => self.retry_queue.push((id, Instant::now())),
+            Err(e) => {
+                // Still print it, it just won't survive a crash
+                eprintln!("Failed to journal {:?}: {}", image_paths, e);
+                self.submit(None, &image_paths, &layout);
+            }
+        }
+    }
+
+    /// Adds the photo to the page being filled and queues the page once it's full.
+    fn add_to_batch(&mut self, image_path: String, layout: PdfImageInserter) {
+        self.batch.push(image_path);
+        // The layout may have changed since the last photo, a batch
+        // that outgrew a smaller one is split into full pages
+        let per_page = layout.images_per_page();
+        while self.batch.len() >= per_page {
+            let page = self.batch.drain(..per_page).collect();
+            self.queue_page(page, layout.clone());
+        }
+        if self.batch.is_empty() {
+            return;
+        }
+
+        let result = match self.batch_id {
+            Some(id) => self.journal.update(id, |entry| {
+                entry.image_paths = self.batch.clone();
+                entry.layout = layout.clone();
+            }),
+            None => self.journal.add_waiting(self.batch.clone(), layout).map(|id| self.batch_id = Some(id)),
+        };
+        if let Err(e) = result {
+            // Still held in memory, it just won't survive a crash
+            eprintln!("Failed to journal {:?}: {}", self.batch, e);
+        }
+        self.send_status(&self.batch, PrintStatus::Waiting { remaining: per_page - self.batch.len() });
+    }
+
+    /// Queues the partly filled page.
+    fn flush_batch(&mut self, layout: PdfImageInserter) {
+        if !self.batch.is_empty() {
+            let page = std::mem::take(&mut self.batch);
+            self.queue_page(page, layout);
+        }
+    }
+
+    /// Queues a page from the batch, reusing the batch's journal entry if it has one.
+    fn queue_page(&mut self, image_paths: Vec<String>, layout: PdfImageInserter) {
+        let Some(id) = self.batch_id.take() else {
+            self.enqueue(image_paths, layout);
+            return;
+        };
+        self.send_status(&image_paths, PrintStatus::Queued);
+        let update = self.journal.update(id, |entry| {
+            entry.image_paths = image_paths.clone();
+            entry.layout = layout.clone();
+            entry.waiting = false;
+        });
+        match update {
+            Ok(()) => self.retry_queue.push((id, Instant::now())),
+            Err(e) => {
+                eprintln!("Failed to journal {:?}: {}", image_paths, e);
+                self.submit(None, &image_paths, &layout);
+            }
+        }
+    }
+
+    /// Drops the retrying jobs of photos the operator cancelled. Requests for
+    /// photos that aren't retrying are discarded.
+    fn cancel_requested(&mut self) {
+        let requests = std::mem::take(&mut *self.cancel_requests.lock());
+        if requests.is_empty() {
+            return;
+        }
+        let (cancelled, waiting): (Vec<_>, Vec<_>) = self.retry_queue.drain(..).partition(|(id, _)| {
+            self.journal
+                .get(*id)
+                .is_some_and(|entry| entry.image_paths.iter().any(|path| requests.contains(path)))
+        });
+        self.retry_queue = waiting;
+
+        for (id, _) in cancelled {
+            let image_paths = self.journal.get(id).map(|e| e.image_paths.clone()).unwrap_or_default();
+            println!("Cancelled printing {:?}", image_paths);
+            self.send_status(&image_paths, PrintStatus::Failed("cancelled".to_string()));
+            self.release(&image_paths);
+            self.discard_pdf(id);
+            self.forget(id);
+        }
+    }
+
+    fn process_due(&mut self) {
+        let now = Instant::now();
+        let (due, waiting): (Vec<_>, Vec<_>) = self.retry_queue.drain(..).partition(|(_, at)| *at <= now);
+        self.retry_queue = waiting;
+
+        for (id, _) in due {
+            let Some(entry) = self.journal.get(id).cloned() else { continue };
+            self.submit(Some(id), &entry.image_paths, &entry.layout);
+        }
+    }
+
+    fn submit(&mut self, id: Option<u64>, image_paths: &[String], layout: &PdfImageInserter) {
+        let PrintSettings { printer_name, options } = self.settings.lock().clone();
+        let save_pdf_path = match self.output_path(id, image_paths, layout) {
+            Ok(path) => path,
+            Err(e) => {
+                self.attempt_failed(id, image_paths, format!("Insert error: {}", e));
+                return;
+            }
+        };
+        // A missing photo or template, or a slot off the template's pages, fails
+        // the same way on every attempt, only the printer is worth waiting for
+        if let Err(e) = layout.insert_image(image_paths, &save_pdf_path) {
+            remove_pdf(&save_pdf_path);
+            self.give_up(id, image_paths, format!("Insert error: {}", e));
+            return;
+        }
+        let result = self
+            .printer(printer_name.as_deref())
+            .map_err(|e| e.to_string())
+            .and_then(|printer| {
+                printer
+                    .print(&save_pdf_path, &options)
+                    .map_err(|e| format!("Print error for {:?}: {}", save_pdf_path, e))
+            });
+
+        match result {
+            Ok(job) => {
+                println!("Successfully sumbited a print job!");
+                self.send_status(image_paths, PrintStatus::Job(job, JobState::Pending));
+                if let Some(id) = id {
+                    let update = self.journal.update(id, |entry| {
+                        entry.job_id = Some(job.id);
+                        entry.printer_name = printer_name.clone();
+                    });
+                    if let Err(e) = update {
+                        eprintln!("Failed to update print journal: {}", e);
+                    }
+                    self.active_jobs.push(ActiveJob { id, printer_name, job, state: JobState::Pending, unreachable_since: None });
+                } else {
+                    // Without a journal entry the job isn't tracked, handing it over is as far as it's followed
+                    self.printed(image_paths);
+                }
+            }
+            Err(error) => {
+                // The next attempt writes the same file again
+                remove_pdf(&save_pdf_path);
+                self.attempt_failed(id, image_paths, error);
+            }
+        }
+    }
+
+    /// The PDF a journaled job is written to, the same one for every attempt.
+    fn output_path(&mut self, id: Option<u64>, image_paths: &[String], layout: &PdfImageInserter) -> Result<PathBuf, Box<dyn Error>> {
+        if let Some(path) = id.and_then(|id| self.journal.get(id)).and_then(|entry| entry.output_path.clone()) {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(|e| format!("Failed to create output folder {:?}: {}", dir, e))?;
+            }
+            return Ok(path);
+        }
+        let path = layout.save_pdf_path(image_paths.first().map(String::as_str).unwrap_or_default())?;
+        if let Some(id) = id {
+            if let Err(e) = self.journal.update(id, |entry| entry.output_path = Some(path.clone())) {
+                eprintln!("Failed to update print journal: {}", e);
+            }
+        }
+        Ok(path)
+    }
+
+    /// Drops a job that can't ever print.
+    fn give_up(&mut self, id: Option<u64>, image_paths: &[String], error: String) {
+        eprintln!("{}", error);
+        self.send_status(image_paths, PrintStatus::Failed(error));
+        self.release(image_paths);
+        if let Some(id) = id {
+            self.discard_pdf(id);
+            self.forget(id);
+        }
+    }
+
+    fn attempt_failed(&mut self, id: Option<u64>, image_paths: &[String], error: String) {
+        eprintln!("{}", error);
+        let Some(id) = id else {
+            self.send_status(image_paths, PrintStatus::Failed(error));
+            self.release(image_paths);
+            return;
+        };
+
+        // An outage can last longer than any fixed number of attempts, the
+        // job stays journaled until it prints or is cancelled
+        let attempts = self.journal.get(id).map(|e| e.attempts + 1).unwrap_or(1);
+        if let Err(e) = self.journal.update(id, |entry| entry.attempts = attempts) {
+            eprintln!("Failed to update print journal: {}", e);
+        }
+        self.send_status(image_paths, PrintStatus::Retrying { attempt: attempts, error });
+        self.retry_queue.push((id, Instant::now() + retry_delay(attempts)));
+    }
+
+    fn poll_jobs(&mut self) {
+        let mut active_jobs = std::mem::take(&mut self.active_jobs);
+        active_jobs.retain_mut(|active| {
+            let image_paths = self.journal.get(active.id).map(|e| e.image_paths.clone()).unwrap_or_default();
+            let state = self
+                .printer(active.printer_name.as_deref())
+                .and_then(|printer| printer.job_state(&active.job));
+
+            match state {
+                Ok(state) => {
+                    active.unreachable_since = None;
+                    if state != active.state {
+                        active.state = state;
+                        self.send_status(&image_paths, PrintStatus::Job(active.job, state));
+                    }
+                    match state {
+                        JobState::Completed => {
+                            self.printed(&image_paths);
+                            self.apply_retention(active.id);
+                        }
+                        // Maybe printed, maybe not, the PDF stays for the operator to check
+                        JobState::Unlisted => self.release(&image_paths),
+                        _ if state.is_finished() => {
+                            self.release(&image_paths);
+                            self.discard_pdf(active.id);
+                        }
+                        _ => {}
+                    }
+                    if state.is_finished() {
+                        self.forget(active.id);
+                        return false;
+                    }
+                    true
+                }
+                Err(e) => {
+                    // A network hiccup or a restarting cupsd says nothing about the job itself
+                    let since = *active.unreachable_since.get_or_insert_with(Instant::now);
+                    if since.elapsed() < JOB_QUERY_TIMEOUT {
+                        eprintln!("Failed to query print job {}, asking again: {}", active.job.id, e);
+                        return true;
+                    }
+                    eprintln!("Giving up on print job {}: {}", active.job.id, e);
+                    // It may still print, so its PDF is kept
+                    self.send_status(&image_paths, PrintStatus::Failed(format!("Lost track of job {}: {}", active.job.id, e)));
+                    self.release(&image_paths);
+                    self.forget(active.id);
+                    false
+                }
+            }
+        });
+        self.active_jobs = active_jobs;
+    }
+
+    fn apply_retention(&self, id: u64) {
+        let Some(entry) = self.journal.get(id) else { return };
+        if let Some(pdf_path) = &entry.output_path {
+            if let Err(e) = entry.layout.output().apply_retention(pdf_path) {
+                eprintln!("{}", e);
+            }
+        }
+    }
+
+    /// The PDF of a job that didn't print is of no use to keep around.
+    fn discard_pdf(&self, id: u64) {
+        if let Some(pdf_path) = self.journal.get(id).and_then(|entry| entry.output_path.as_ref()) {
+            remove_pdf(pdf_path);
+        }
+    }
+
+    fn forget(&mut self, id: u64) {
+        if let Err(e) = self.journal.remove(id) {
+            eprintln!("Failed to update print journal: {}", e);
+        }
+    }
+}
+
+fn remove_pdf(pdf_path: &Path) {
+    match fs::remove_file(pdf_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => eprintln!("Failed to delete {:?}: {}", pdf_path, e),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A landscape photo.
+    const ASPECT: f32 = 1.5;
+    const ANGLES: [f32; 3] = [0.0, 90.0, 30.0];
+
+    fn slot(fit: FitMode, rotation: f32) -> Slot {
+        Slot { fit, rotation, ..Slot::new(10.0, 20.0, 100.0, 200.0) }
+    }
+
+    fn apply(m: [f32; 6], (u, v): (f32, f32)) -> (f32, f32) {
+        (m[0] * u + m[2] * v + m[4], m[1] * u + m[3] * v + m[5])
+    }
+
+    /// Where the corners of the photo's unit square end up, as min x, min y, max x, max y.
+    fn bounds(m: [f32; 6]) -> (f32, f32, f32, f32) {
+        [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].into_iter().map(|corner| apply(m, corner)).fold(
+            (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+            |(x0, y0, x1, y1), (x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        )
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    /// Side lengths of the drawn photo, which keep its aspect unless stretched.
+    fn drawn_aspect(m: [f32; 6]) -> f32 {
+        m[0].hypot(m[1]) / m[2].hypot(m[3])
+    }
+
+    #[test]
+    fn contain_fits_the_whole_photo_into_the_slot() {
+        for angle in ANGLES {
+            let slot = slot(FitMode::Contain, angle);
+            let m = slot.image_matrix(ASPECT);
+            let (x0, y0, x1, y1) = bounds(m);
+            assert!(x0 >= 10.0 - 1e-3 && y0 >= 20.0 - 1e-3 && x1 <= 110.0 + 1e-3 && y1 <= 220.0 + 1e-3, "{}°: {:?}", angle, (x0, y0, x1, y1));
+            // Touching the slot on two sides, centered on the other axis
+            assert!(close(x1 - x0, 100.0) || close(y1 - y0, 200.0), "{}°", angle);
+            assert!(close(x0 + x1, 120.0) && close(y0 + y1, 240.0), "{}°", angle);
+            assert!(close(drawn_aspect(m), ASPECT), "{}°", angle);
+        }
+    }
+
+    #[test]
+    fn cover_fills_the_slot() {
+        for angle in ANGLES {
+            let slot = slot(FitMode::Cover, angle);
+            let m = slot.image_matrix(ASPECT);
+            let (x0, y0, x1, y1) = bounds(m);
+            assert!(x0 <= 10.0 + 1e-3 && y0 <= 20.0 + 1e-3 && x1 >= 110.0 - 1e-3 && y1 >= 220.0 - 1e-3, "{}°: {:?}", angle, (x0, y0, x1, y1));
+            assert!(close(x1 - x0, 100.0) || close(y1 - y0, 200.0), "{}°", angle);
+            assert!(close(x0 + x1, 120.0) && close(y0 + y1, 240.0), "{}°", angle);
+            assert!(close(drawn_aspect(m), ASPECT), "{}°", angle);
+        }
+    }
+
+    #[test]
+    fn stretch_takes_the_slot_size() {
+        let m = slot(FitMode::Stretch, 0.0).image_matrix(ASPECT);
+        assert_eq!(m, [100.0, 0.0, 0.0, 200.0, 10.0, 20.0]);
+
+        // Turned a quarter, the photo's sides swap so it still exactly covers the slot
+        let m = slot(FitMode::Stretch, 90.0).image_matrix(ASPECT);
+        let (x0, y0, x1, y1) = bounds(m);
+        assert!(close(x0, 10.0) && close(y0, 20.0) && close(x1, 110.0) && close(y1, 220.0), "{:?}", (x0, y0, x1, y1));
+        // The photo's bottom edge runs up the slot
+        assert!(close(m[0], 0.0) && m[1] > 0.0);
+
+        let slot = slot(FitMode::Stretch, 30.0);
+        let m = slot.image_matrix(ASPECT);
+        assert_eq!(slot.image_size(ASPECT), (100.0, 200.0));
+        assert!(close(m[0].hypot(m[1]), 100.0) && close(m[2].hypot(m[3]), 200.0));
+        let (cx, cy) = apply(m, (0.5, 0.5));
+        assert!(close(cx, 60.0) && close(cy, 120.0));
+    }
+
+    #[test]
+    fn focus_fills_the_slot_around_the_focal_point() {
+        for angle in ANGLES {
+            let slot = Slot { focus: (0.0, 0.0), ..slot(FitMode::Focus, angle) };
+            let m = slot.image_matrix(ASPECT);
+            let (x0, y0, x1, y1) = bounds(m);
+            assert!(x0 <= 10.0 + 1e-3 && y0 <= 20.0 + 1e-3 && x1 >= 110.0 - 1e-3 && y1 >= 220.0 - 1e-3, "{}°: {:?}", angle, (x0, y0, x1, y1));
+            assert!(close(drawn_aspect(m), ASPECT), "{}°", angle);
+        }
+
+        // Upright, the photo's top-left corner (0, 1 in image space) sits on the slot's
+        let m = Slot { focus: (0.0, 0.0), ..slot(FitMode::Focus, 0.0) }.image_matrix(ASPECT);
+        let (x, y) = apply(m, (0.0, 1.0));
+        assert!(close(x, 10.0) && close(y, 220.0), "{:?}", (x, y));
+        // and its bottom-right one on the slot's for the opposite focus
+        let m = Slot { focus: (1.0, 1.0), ..slot(FitMode::Focus, 0.0) }.image_matrix(ASPECT);
+        let (x, y) = apply(m, (1.0, 0.0));
+        assert!(close(x, 110.0) && close(y, 20.0), "{:?}", (x, y));
+
+        // Turned a quarter, the photo's top-left corner ends up at the slot's bottom-left
+        let m = Slot { focus: (0.0, 0.0), ..slot(FitMode::Focus, 90.0) }.image_matrix(ASPECT);
+        let (x, y) = apply(m, (0.0, 1.0));
+        assert!(close(x, 10.0) && close(y, 20.0), "{:?}", (x, y));
+
+        // A centered focus is the same as cover
+        let centered = slot(FitMode::Focus, 30.0).image_matrix(ASPECT);
+        let cover = slot(FitMode::Cover, 30.0).image_matrix(ASPECT);
+        assert!(centered.iter().zip(cover).all(|(a, b)| close(*a, b)));
+    }
+}
@@ -1,60 +1,440 @@
 use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::ipp::{self, IppEndpoint, IppRequest, IppResponse, IppValue};
 #[cfg(target_os = "windows")]
 pub use winprint::printer::{PdfiumPrinter, PrinterDevice, FilePrinter};
+#[cfg(target_os = "windows")]
+use winprint::ticket::{
+    Copies, FeatureOptionPack, FeatureOptionPackWithPredefined, PredefinedDuplexType, PredefinedMediaName,
+    PredefinedPageOrientation, PredefinedPageOutputColor, PrintCapabilities, PrintTicket, PrintTicketBuilder,
+};
 
-#[cfg(target_os = "macos")]
+#[cfg(any(target_os = "macos", target_os = "linux"))]
 mod bindings {
     #![allow(warnings)]
     include!(concat!(env!("OUT_DIR"), "/bindings_cups.rs"));
 }
-#[cfg(target_os = "macos")]
-use std::ffi::CString;
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+use std::ffi::{CStr, CString};
+
+
+/// Handle to a submitted print job, used to poll its state afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintJob {
+    pub id: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Pending,
+    Processing,
+    Completed,
+    Aborted,
+    Cancelled,
+    /// The printer no longer knows the job, so whether it printed is unconfirmed.
+    Unlisted,
+}
+
+impl JobState {
+    /// Maps an IPP `job-state` enum value (also used by CUPS' `ipp_jstate_t`).
+    pub fn from_ipp(state: i32) -> JobState {
+        match state {
+            // pending, pending-held
+            3 | 4 => JobState::Pending,
+            // processing, processing-stopped
+            5 | 6 => JobState::Processing,
+            7 => JobState::Cancelled,
+            8 => JobState::Aborted,
+            9 => JobState::Completed,
+            _ => JobState::Pending,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Completed | JobState::Aborted | JobState::Cancelled | JobState::Unlisted)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            JobState::Pending => "pending",
+            JobState::Processing => "processing",
+            JobState::Completed => "completed",
+            JobState::Aborted => "aborted",
+            JobState::Cancelled => "cancelled",
+            JobState::Unlisted => "no longer listed, unconfirmed",
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PaperSize {
+    #[default]
+    PrinterDefault,
+    A4,
+    A5,
+    A6,
+    Letter,
+    Photo4x6,
+    Photo5x7,
+}
+
+impl PaperSize {
+    pub const ALL: [PaperSize; 7] = [
+        PaperSize::PrinterDefault,
+        PaperSize::A4,
+        PaperSize::A5,
+        PaperSize::A6,
+        PaperSize::Letter,
+        PaperSize::Photo4x6,
+        PaperSize::Photo5x7,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PaperSize::PrinterDefault => "Printer default",
+            PaperSize::A4 => "A4",
+            PaperSize::A5 => "A5",
+            PaperSize::A6 => "A6",
+            PaperSize::Letter => "Letter",
+            PaperSize::Photo4x6 => "4x6 in",
+            PaperSize::Photo5x7 => "5x7 in",
+        }
+    }
+
+    /// PWG 5101.1 media name, understood by both CUPS and IPP printers.
+    fn pwg_name(&self) -> Option<&'static str> {
+        match self {
+            PaperSize::PrinterDefault => None,
+            PaperSize::A4 => Some("iso_a4_210x297mm"),
+            PaperSize::A5 => Some("iso_a5_148x210mm"),
+            PaperSize::A6 => Some("iso_a6_105x148mm"),
+            PaperSize::Letter => Some("na_letter_8.5x11in"),
+            PaperSize::Photo4x6 => Some("na_index-4x6_4x6in"),
+            PaperSize::Photo5x7 => Some("na_5x7_5x7in"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    Landscape,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 2] = [Orientation::Portrait, Orientation::Landscape];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Orientation::Portrait => "Portrait",
+            Orientation::Landscape => "Landscape",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Duplex {
+    #[default]
+    Off,
+    LongEdge,
+    ShortEdge,
+}
+
+impl Duplex {
+    pub const ALL: [Duplex; 3] = [Duplex::Off, Duplex::LongEdge, Duplex::ShortEdge];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Duplex::Off => "One-sided",
+            Duplex::LongEdge => "Two-sided, long edge",
+            Duplex::ShortEdge => "Two-sided, short edge",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ColorMode {
+    #[default]
+    Color,
+    Monochrome,
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 2] = [ColorMode::Color, ColorMode::Monochrome];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorMode::Color => "Color",
+            ColorMode::Monochrome => "Monochrome",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PrintQuality {
+    Draft,
+    #[default]
+    Normal,
+    High,
+}
+
+impl PrintQuality {
+    pub const ALL: [PrintQuality; 3] = [PrintQuality::Draft, PrintQuality::Normal, PrintQuality::High];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PrintQuality::Draft => "Draft",
+            PrintQuality::Normal => "Normal",
+            PrintQuality::High => "High",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrintOptions {
+    pub copies: u32,
+    pub paper_size: PaperSize,
+    pub orientation: Orientation,
+    pub duplex: Duplex,
+    pub color_mode: ColorMode,
+    pub quality: PrintQuality,
+    pub fit_to_page: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            copies: 1,
+            paper_size: PaperSize::default(),
+            orientation: Orientation::default(),
+            duplex: Duplex::default(),
+            color_mode: ColorMode::default(),
+            quality: PrintQuality::default(),
+            fit_to_page: false,
+        }
+    }
+}
+
+enum OptionValue {
+    Integer(i32),
+    Enum(i32),
+    Keyword(&'static str),
+}
+
+impl OptionValue {
+    #[cfg_attr(not(any(target_os = "macos", target_os = "linux")), allow(dead_code))]
+    fn to_option_string(&self) -> String {
+        match self {
+            OptionValue::Integer(v) | OptionValue::Enum(v) => v.to_string(),
+            OptionValue::Keyword(v) => v.to_string(),
+        }
+    }
+}
+
+impl PrintOptions {
+    /// The options as IPP job template attributes, which CUPS also accepts as
+    /// its option names.
+    fn job_attributes(&self) -> Vec<(&'static str, OptionValue)> {
+        let mut attributes = vec![
+            ("copies", OptionValue::Integer(self.copies.clamp(1, i32::MAX as u32) as i32)),
+            ("orientation-requested", OptionValue::Enum(match self.orientation {
+                Orientation::Portrait => 3,
+                Orientation::Landscape => 4,
+            })),
+            ("sides", OptionValue::Keyword(match self.duplex {
+                Duplex::Off => "one-sided",
+                Duplex::LongEdge => "two-sided-long-edge",
+                Duplex::ShortEdge => "two-sided-short-edge",
+            })),
+            ("print-color-mode", OptionValue::Keyword(match self.color_mode {
+                ColorMode::Color => "color",
+                ColorMode::Monochrome => "monochrome",
+            })),
+            ("print-quality", OptionValue::Enum(match self.quality {
+                PrintQuality::Draft => 3,
+                PrintQuality::Normal => 4,
+                PrintQuality::High => 5,
+            })),
+            // Without fitting the page is printed at its real size, so placements stay exact
+            ("print-scaling", OptionValue::Keyword(if self.fit_to_page { "fit" } else { "none" })),
+        ];
+        if let Some(media) = self.paper_size.pwg_name() {
+            attributes.push(("media", OptionValue::Keyword(media)));
+        }
+        attributes
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrinterInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub is_online: bool,
+    pub media: Vec<String>,
+}
 
 
 pub trait Printer: Send  + 'static   {
     fn new(printer_name: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> where Self : Sized;
 
-    fn print(&self, file_to_print: &Path) -> Result<(), Box<dyn std::error::Error>>;
+    fn available() -> Result<Vec<PrinterInfo>, Box<dyn std::error::Error>> where Self : Sized;
+
+    fn print(&self, file_to_print: &Path, options: &PrintOptions) -> Result<PrintJob, Box<dyn std::error::Error>>;
+
+    fn job_state(&self, job: &PrintJob) -> Result<JobState, Box<dyn std::error::Error>>;
 }
 
 #[cfg(target_os = "windows")]
 pub struct WindowsPrinter {
+    device: PrinterDevice,
     printer: PdfiumPrinter,
 }
+
+#[cfg(target_os = "windows")]
+const PRINTER_ATTRIBUTE_DEFAULT: u32 = 0x0000_0004;
+#[cfg(target_os = "windows")]
+const PRINTER_ATTRIBUTE_WORK_OFFLINE: u32 = 0x0000_0400;
+
 #[cfg(target_os = "windows")]
 impl Printer for WindowsPrinter {
     fn new(printer_name: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
         let devices = PrinterDevice::all()?; 
-        let dev = printer_name
-            .and_then(|name| devices.iter().find(|d| d.name() == name).cloned())
-            .or_else(|| devices.into_iter().next())
-            .ok_or("No printers available")?;
+        let dev = match printer_name {
+            Some(name) => devices
+                .into_iter()
+                .find(|d| d.name() == name)
+                .ok_or_else(|| format!("Printer {:?} not found", name))?,
+            None => {
+                let default = devices.iter().position(|d| d.os_attributes() & PRINTER_ATTRIBUTE_DEFAULT != 0);
+                devices
+                    .into_iter()
+                    .nth(default.unwrap_or(0))
+                    .ok_or("No printers available")?
+            }
+        };
 
-        Ok(WindowsPrinter { printer: PdfiumPrinter::new(dev) })
+        Ok(WindowsPrinter { device: dev.clone(), printer: PdfiumPrinter::new(dev) })
     }
 
-    fn print(&self, file_to_print: &Path) -> Result<(), Box<dyn std::error::Error>> {
-        self.printer
-            .print(file_to_print, Default::default())
-            .map_err(|e| e.into())
+    fn available() -> Result<Vec<PrinterInfo>, Box<dyn std::error::Error>> {
+        let devices = PrinterDevice::all()?;
+        Ok(devices
+            .iter()
+            .map(|dev| {
+                let media = PrintCapabilities::fetch(dev)
+                    .map(|caps| caps.page_media_sizes().filter_map(|m| m.display_name().map(str::to_string)).collect())
+                    .unwrap_or_default();
+                PrinterInfo {
+                    name: dev.name().to_string(),
+                    is_default: dev.os_attributes() & PRINTER_ATTRIBUTE_DEFAULT != 0,
+                    is_online: dev.os_attributes() & PRINTER_ATTRIBUTE_WORK_OFFLINE == 0,
+                    media,
+                }
+            })
+            .collect())
+    }
+
+    fn print(&self, file_to_print: &Path, options: &PrintOptions) -> Result<PrintJob, Box<dyn std::error::Error>> {
+        let ticket = self.print_ticket(options)?;
+        self.printer.print(file_to_print, ticket)?;
+        Ok(PrintJob { id: 0 })
+    }
+
+    fn job_state(&self, _job: &PrintJob) -> Result<JobState, Box<dyn std::error::Error>> {
+        // winprint hands the document to the spooler synchronously and exposes no job id
+        Ok(JobState::Completed)
+    }
+}
+
+
+
+#[cfg(target_os = "windows")]
+impl WindowsPrinter {
+    /// Only options the driver lists in its capabilities are merged, anything
+    /// else keeps the driver default. winprint always scales pages to the
+    /// printable area, so `fit_to_page` has no effect here.
+    fn print_ticket(&self, options: &PrintOptions) -> Result<PrintTicket, Box<dyn std::error::Error>> {
+        let capabilities = PrintCapabilities::fetch(&self.device)?;
+        let mut builder = PrintTicketBuilder::new(&self.device)?;
+
+        builder.merge(Copies(options.copies.clamp(1, u16::MAX as u32) as u16))?;
+
+        let media = match options.paper_size {
+            PaperSize::PrinterDefault => None,
+            PaperSize::A4 => Some(PredefinedMediaName::ISOA4),
+            PaperSize::A5 => Some(PredefinedMediaName::ISOA5),
+            PaperSize::A6 => Some(PredefinedMediaName::ISOA6),
+            PaperSize::Letter => Some(PredefinedMediaName::NorthAmericaLetter),
+            PaperSize::Photo4x6 => Some(PredefinedMediaName::NorthAmerica4x6),
+            PaperSize::Photo5x7 => Some(PredefinedMediaName::NorthAmerica5x7),
+        };
+        if let Some(media) = media.and_then(|name| capabilities.page_media_sizes().find(|m| m.as_predefined_name() == Some(name))) {
+            builder.merge(media)?;
+        }
+
+        let orientation = match options.orientation {
+            Orientation::Portrait => PredefinedPageOrientation::Portrait,
+            Orientation::Landscape => PredefinedPageOrientation::Landscape,
+        };
+        if let Some(orientation) = capabilities.page_orientations().find(|o| o.as_predefined_name() == Some(orientation)) {
+            builder.merge(orientation)?;
+        }
+
+        let duplex = match options.duplex {
+            Duplex::Off => PredefinedDuplexType::OneSided,
+            Duplex::LongEdge => PredefinedDuplexType::TwoSidedLongEdge,
+            Duplex::ShortEdge => PredefinedDuplexType::TwoSidedShortEdge,
+        };
+        if let Some(duplex) = capabilities.duplexes().find(|d| d.as_predefined_name() == Some(duplex)) {
+            builder.merge(duplex)?;
+        }
+
+        let color = match options.color_mode {
+            ColorMode::Color => PredefinedPageOutputColor::Color,
+            ColorMode::Monochrome => PredefinedPageOutputColor::Monochrome,
+        };
+        if let Some(color) = capabilities.page_output_colors().find(|c| c.as_predefined_name() == Some(color)) {
+            builder.merge(color)?;
+        }
+
+        // Drivers describe quality as resolutions, pick the lowest, middle or highest one
+        let mut resolutions: Vec<_> = capabilities.page_resolutions().collect();
+        resolutions.sort_by_key(|r| {
+            let (x, y) = r.dpi();
+            x * y
+        });
+        let index = match options.quality {
+            PrintQuality::Draft => 0,
+            PrintQuality::Normal => resolutions.len() / 2,
+            PrintQuality::High => resolutions.len().saturating_sub(1),
+        };
+        if index < resolutions.len() {
+            builder.merge(resolutions.swap_remove(index))?;
+        }
+
+        Ok(builder.build()?)
     }
 }
 
 
 
-#[cfg(target_os = "macos")]
-pub struct MacosPrinter {
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub struct CupsPrinter {
     dest: *mut bindings::cups_dest_t,
     job_name: CString,
 }
 
 
-#[cfg(target_os = "macos")]
-unsafe impl Send for MacosPrinter {}
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+unsafe impl Send for CupsPrinter {}
 
 
-#[cfg(target_os = "macos")]
-impl Printer for MacosPrinter {
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+impl Printer for CupsPrinter {
     fn new(printer_name: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
         if let Some(name) = printer_name {
             println!("Given printer name: {:?}", name);
@@ -70,16 +450,10 @@ impl Printer for MacosPrinter {
                     std::ptr::null(),
                 );
 
-                if !named.is_null() {
-                    named
-                } else {
-                    // Fallback to default
-                    bindings::cupsGetNamedDest(
-                        std::ptr::null_mut(),
-                        std::ptr::null(),
-                        std::ptr::null(),
-                    )
+                if named.is_null() {
+                    return Err(format!("Printer {:?} not found", name).into());
                 }
+                named
             } else {
                 bindings::cupsGetNamedDest(
                     std::ptr::null_mut(),
@@ -92,34 +466,164 @@ impl Printer for MacosPrinter {
                 return Err("Requested printer not found".into());
             }
 
-            Ok(MacosPrinter {   
+            Ok(CupsPrinter {   
                 dest,
                 job_name: CString::new("Cross-Platform Print Job")?,
             })
         }
     }
 
-    fn print(&self, file_to_print: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fn print(&self, file_to_print: &Path, options: &PrintOptions) -> Result<PrintJob, Box<dyn std::error::Error>> {
         unsafe {
             let file_path = CString::new(file_to_print.to_string_lossy().as_ref())?;
+
+            // The destination's own options, overridden by the requested ones
+            let mut num_options = 0;
+            let mut cups_options: *mut bindings::cups_option_t = std::ptr::null_mut();
+            for i in 0..(*self.dest).num_options as usize {
+                let option = *(*self.dest).options.add(i);
+                num_options = bindings::cupsAddOption(option.name, option.value, num_options, &mut cups_options);
+            }
+            for (name, value) in options.job_attributes() {
+                let name = CString::new(name)?;
+                let value = CString::new(value.to_option_string())?;
+                num_options = bindings::cupsAddOption(name.as_ptr(), value.as_ptr(), num_options, &mut cups_options);
+            }
+
             let job_id = bindings::cupsPrintFile(
                 (*self.dest).name,
                 file_path.as_ptr(),
                 self.job_name.as_ptr(),
-                (*self.dest).num_options,
-                (*self.dest).options,
+                num_options,
+                cups_options,
             );
+            bindings::cupsFreeOptions(num_options, cups_options);
             if job_id == 0 {
                 return Err("Failed to print file".into());
             } else {
                 println!("Print job submitted with ID: {}", job_id);
             }
+            Ok(PrintJob { id: job_id })
+        }
+    }
+
+    fn available() -> Result<Vec<PrinterInfo>, Box<dyn std::error::Error>> {
+        unsafe {
+            let mut dests: *mut bindings::cups_dest_t = std::ptr::null_mut();
+            let count = bindings::cupsGetDests(&mut dests);
+            if count <= 0 {
+                return Ok(Vec::new());
+            }
+
+            let printers = std::slice::from_raw_parts_mut(dests, count as usize)
+                .iter_mut()
+                .map(|dest| cups_printer_info(dest))
+                .collect();
+            bindings::cupsFreeDests(count, dests);
+            Ok(printers)
         }
-        Ok(())
+    }
+
+    /// Asks the scheduler about this one job instead of listing its whole history.
+    fn job_state(&self, job: &PrintJob) -> Result<JobState, Box<dyn std::error::Error>> {
+        let job_uri = CString::new(format!("ipp://localhost/jobs/{}", job.id))?;
+        let job_uri_name = CString::new("job-uri")?;
+        let requested_name = CString::new("requested-attributes")?;
+        let job_state_name = CString::new("job-state")?;
+        let resource = CString::new("/")?;
+        unsafe {
+            let request = bindings::ippNewRequest(ipp::operation::GET_JOB_ATTRIBUTES as _);
+            bindings::ippAddString(
+                request,
+                ipp::tag::OPERATION_ATTRIBUTES as _,
+                ipp::tag::URI as _,
+                job_uri_name.as_ptr(),
+                std::ptr::null(),
+                job_uri.as_ptr(),
+            );
+            bindings::ippAddString(
+                request,
+                ipp::tag::OPERATION_ATTRIBUTES as _,
+                ipp::tag::KEYWORD as _,
+                requested_name.as_ptr(),
+                std::ptr::null(),
+                job_state_name.as_ptr(),
+            );
+            // Frees the request
+            let response = bindings::cupsDoRequest(std::ptr::null_mut(), request, resource.as_ptr());
+            let status = bindings::cupsLastError() as u16;
+
+            let state = if status == ipp::status::CLIENT_ERROR_NOT_FOUND {
+                // The id came from CUPS, so the job is gone because it ended and the
+                // server doesn't keep a history (PreserveJobHistory No), how it ended is lost
+                println!("Job {} is no longer listed by CUPS", job.id);
+                Ok(JobState::Unlisted)
+            } else if response.is_null() || status >= 0x0100 {
+                Err(format!(
+                    "CUPS couldn't report on job {}: {}",
+                    job.id,
+                    cups_string(bindings::cupsLastErrorString()).unwrap_or_default()
+                )
+                .into())
+            } else {
+                let attribute = bindings::ippFindAttribute(response, job_state_name.as_ptr(), ipp::tag::ENUM as _);
+                if attribute.is_null() {
+                    Err(format!("CUPS did not report a job-state for job {}", job.id).into())
+                } else {
+                    Ok(JobState::from_ipp(bindings::ippGetInteger(attribute, 0)))
+                }
+            };
+            if !response.is_null() {
+                bindings::ippDelete(response);
+            }
+            state
+        }
+    }
+}
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+unsafe fn cups_string(value: *const std::os::raw::c_char) -> Option<String> {
+    if value.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(value).to_string_lossy().into_owned())
     }
 }
-#[cfg(target_os = "macos")]
-impl Drop for MacosPrinter {
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+unsafe fn cups_printer_info(dest: *mut bindings::cups_dest_t) -> PrinterInfo {
+    let option = |key: &str| {
+        let key = CString::new(key).ok()?;
+        cups_string(bindings::cupsGetOption(key.as_ptr(), (*dest).num_options, (*dest).options))
+    };
+    // printer-state 5 is "stopped"
+    let is_online = option("printer-state").as_deref() != Some("5")
+        && option("printer-is-accepting-jobs").as_deref() != Some("false");
+
+    let mut media = Vec::new();
+    let info = bindings::cupsCopyDestInfo(std::ptr::null_mut(), dest);
+    if !info.is_null() {
+        let media_option = CString::new("media").unwrap();
+        let supported = bindings::cupsFindDestSupported(std::ptr::null_mut(), dest, info, media_option.as_ptr());
+        if !supported.is_null() {
+            for i in 0..bindings::ippGetCount(supported) {
+                if let Some(name) = cups_string(bindings::ippGetString(supported, i, std::ptr::null_mut())) {
+                    media.push(name);
+                }
+            }
+        }
+        bindings::cupsFreeDestInfo(info);
+    }
+
+    PrinterInfo {
+        name: cups_string((*dest).name).unwrap_or_default(),
+        is_default: (*dest).is_default != 0,
+        is_online,
+        media,
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+impl Drop for CupsPrinter {
     fn drop(&mut self) {
         unsafe {
             bindings::cupsFreeDests(1, self.dest);
@@ -128,13 +632,154 @@ impl Drop for MacosPrinter {
 }
 
 
+/// Talks IPP directly to a driverless (IPP Everywhere) printer, so no platform
+/// print system is involved. The printer name is its `ipp://` uri.
+pub struct IppPrinter {
+    endpoint: IppEndpoint,
+    printer_uri: String,
+    job_name: String,
+    user_name: String,
+}
+
+impl IppPrinter {
+    fn request(&self, operation: u16) -> IppRequest {
+        let mut request = IppRequest::new(operation, &self.printer_uri);
+        request.add_name(ipp::tag::OPERATION_ATTRIBUTES, "requesting-user-name", &self.user_name);
+        request
+    }
+
+    fn send(&self, request: &IppRequest) -> Result<IppResponse, Box<dyn std::error::Error>> {
+        let response = self.endpoint.send(request)?;
+        if !response.is_successful() {
+            return Err(format!("Printer {} rejected the request: {}", self.printer_uri, response.status_message()).into());
+        }
+        Ok(response)
+    }
+
+    pub fn get_printer_attributes(&self, attributes: &[&str]) -> Result<IppResponse, Box<dyn std::error::Error>> {
+        let mut request = self.request(ipp::operation::GET_PRINTER_ATTRIBUTES);
+        request.add_keywords(ipp::tag::OPERATION_ATTRIBUTES, "requested-attributes", attributes);
+        self.send(&request)
+    }
+
+    fn get_job_state(&self, job_id: i32) -> Result<JobState, Box<dyn std::error::Error>> {
+        let mut request = self.request(ipp::operation::GET_JOB_ATTRIBUTES);
+        request
+            .add(ipp::tag::OPERATION_ATTRIBUTES, "job-id", IppValue::Integer(job_id))
+            .add_keywords(ipp::tag::OPERATION_ATTRIBUTES, "requested-attributes", &["job-state"]);
+        let response = self.endpoint.send(&request)?;
+        // Printers only keep a short job history, a job that got an id but is no
+        // longer known has ended one way or another
+        if response.status == ipp::status::CLIENT_ERROR_NOT_FOUND {
+            println!("Job {} is no longer listed by {}", job_id, self.printer_uri);
+            return Ok(JobState::Unlisted);
+        }
+        if !response.is_successful() {
+            return Err(format!("Printer {} rejected the request: {}", self.printer_uri, response.status_message()).into());
+        }
+        response
+            .get_value(ipp::tag::JOB_ATTRIBUTES, "job-state")
+            .and_then(IppValue::as_i32)
+            .map(JobState::from_ipp)
+            .ok_or_else(|| "Printer did not report a job-state".into())
+    }
+}
+
+impl Printer for IppPrinter {
+    fn new(printer_name: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let printer_uri = printer_name.ok_or("IPP printer requires a printer uri")?;
+        let printer = IppPrinter {
+            endpoint: IppEndpoint::parse(printer_uri)?,
+            printer_uri: printer_uri.to_string(),
+            job_name: "Cross-Platform Print Job".to_string(),
+            user_name: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| "photoQT".to_string()),
+        };
+
+        let response = printer.get_printer_attributes(&["printer-state", "document-format-supported"])?;
+        let supports_pdf = response
+            .get(ipp::tag::PRINTER_ATTRIBUTES, "document-format-supported")
+            .map(|a| a.values.iter().any(|v| v.as_str() == Some("application/pdf")))
+            .unwrap_or(true);
+        if !supports_pdf {
+            return Err(format!("Printer {} does not accept PDF documents", printer_uri).into());
+        }
+        println!(
+            "Connected to IPP printer {} (printer-state {:?})",
+            printer_uri,
+            response.get_value(ipp::tag::PRINTER_ATTRIBUTES, "printer-state").and_then(IppValue::as_i32)
+        );
+
+        Ok(printer)
+    }
+
+    fn available() -> Result<Vec<PrinterInfo>, Box<dyn std::error::Error>> {
+        // IPP printers are addressed by uri, there is no local registry to list
+        Ok(Vec::new())
+    }
+
+    fn print(&self, file_to_print: &Path, options: &PrintOptions) -> Result<PrintJob, Box<dyn std::error::Error>> {
+        let document = std::fs::read(file_to_print)
+            .map_err(|e| format!("Failed to read {:?}: {}", file_to_print, e))?;
+
+        let mut request = self.request(ipp::operation::PRINT_JOB);
+        request
+            .add_name(ipp::tag::OPERATION_ATTRIBUTES, "job-name", &self.job_name)
+            .add_mime_type(ipp::tag::OPERATION_ATTRIBUTES, "document-format", "application/pdf");
+        for (name, value) in options.job_attributes() {
+            match value {
+                OptionValue::Integer(v) => request.add(ipp::tag::JOB_ATTRIBUTES, name, IppValue::Integer(v)),
+                OptionValue::Enum(v) => request.add(ipp::tag::JOB_ATTRIBUTES, name, IppValue::Enum(v)),
+                OptionValue::Keyword(v) => request.add_keyword(ipp::tag::JOB_ATTRIBUTES, name, v),
+            };
+        }
+        request.with_document(document);
+        let response = self.send(&request)?;
+
+        let job_id = response
+            .get_value(ipp::tag::JOB_ATTRIBUTES, "job-id")
+            .and_then(IppValue::as_i32)
+            .ok_or("Printer did not return a job-id")?;
+        println!("Print job submitted with ID: {}", job_id);
+        Ok(PrintJob { id: job_id })
+    }
+
+    fn job_state(&self, job: &PrintJob) -> Result<JobState, Box<dyn std::error::Error>> {
+        self.get_job_state(job.id)
+    }
+}
+
+/// Only the schemes `IppEndpoint` can talk to, encrypted ones are left to the
+/// platform's print system.
+fn is_ipp_uri(printer_name: &str) -> bool {
+    printer_name.starts_with("ipp://") || printer_name.starts_with("http://")
+}
+
+
+pub fn list_printers() -> Result<Vec<PrinterInfo>, Box<dyn std::error::Error>> {
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    { CupsPrinter::available() }
+
+    #[cfg(target_os = "windows")]
+    { WindowsPrinter::available() }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    { IppPrinter::available() }
+}
+
+
 pub fn make_printer(printer_name: Option<&str>) -> Result<Box<dyn Printer>, Box<dyn std::error::Error>> {
-    #[cfg(target_os = "macos")]
-    { Ok(Box::new(MacosPrinter::new(printer_name)?)) }
+    if let Some(uri) = printer_name.filter(|name| is_ipp_uri(name)) {
+        return Ok(Box::new(IppPrinter::new(Some(uri))?));
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    { Ok(Box::new(CupsPrinter::new(printer_name)?)) }
 
     #[cfg(target_os = "windows")]
     { Ok(Box::new(WindowsPrinter::new(printer_name)?)) }
 
-    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
-    { Err("Unsupported platform".into()) }
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    { Err("Unsupported platform, use an ipp:// printer uri".into()) }
 }
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    error::Error,
+    path::PathBuf,
+};
+
+use crate::printer::{PdfImageInserter, PrintSettings};
+use crate::store;
+
+
+/// Everything needed to pick up a photo booth setup again: the template with
+/// its slots, and where and how it gets printed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutProfile {
+    pub name: String,
+    pub layout: PdfImageInserter,
+    #[serde(default)]
+    pub print: PrintSettings,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProfilesData {
+    last_used: Option<String>,
+    profiles: Vec<LayoutProfile>,
+}
+
+/// Named layout profiles saved on disk, along with the one used last.
+pub struct Profiles {
+    path: PathBuf,
+    data: ProfilesData,
+}
+
+impl Profiles {
+
+    pub fn default_path() -> PathBuf {
+        env::current_dir().unwrap().join("layout_profiles.json")
+    }
+
+    pub fn open(path: PathBuf) -> Result<Profiles, Box<dyn Error>> {
+        let data = store::load(&path, "profiles")?;
+        Ok(Profiles { path, data })
+    }
+
+    /// Starts without saved profiles if the file is unreadable, see `store::load_or_reset`.
+    pub fn open_or_reset(path: PathBuf) -> Profiles {
+        let data = store::load_or_reset(&path, "profiles");
+        Profiles { path, data }
+    }
+
+    pub fn profiles(&self) -> &[LayoutProfile] {
+        &self.data.profiles
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LayoutProfile> {
+        self.data.profiles.iter().find(|p| p.name == name)
+    }
+
+    pub fn last_used(&self) -> Option<&LayoutProfile> {
+        self.data.last_used.as_deref().and_then(|name| self.get(name))
+    }
+
+    /// Adds the profile or replaces the one with the same name, and makes it the last used.
+    pub fn save_profile(&mut self, profile: LayoutProfile) -> Result<(), Box<dyn Error>> {
+        self.data.last_used = Some(profile.name.clone());
+        match self.data.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.data.profiles.push(profile),
+        }
+        self.save()
+    }
+
+    pub fn set_last_used(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        self.data.last_used = Some(name.to_string());
+        self.save()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        self.data.profiles.retain(|p| p.name != name);
+        if self.data.last_used.as_deref() == Some(name) {
+            self.data.last_used = None;
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        store::save(&self.path, "profiles", &self.data)
+    }
+}
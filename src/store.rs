@@ -0,0 +1,40 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+};
+
+
+/// Reads the JSON file at `path`, or the default if there is none yet. `what`
+/// names the file in error messages.
+pub fn load<T: DeserializeOwned + Default>(path: &Path, what: &str) -> Result<T, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let raw = fs::read_to_string(path).map_err(|e| format!("Failed to read {} {:?}: {}", what, path, e))?;
+    Ok(serde_json::from_str(&raw).map_err(|e| format!("Failed to parse {} {:?}: {}", what, path, e))?)
+}
+
+/// Like `load`, but moves an unreadable file aside and starts from the default
+/// instead of failing, so the next save can't overwrite what was in it.
+pub fn load_or_reset<T: DeserializeOwned + Default>(path: &Path, what: &str) -> T {
+    load(path, what).unwrap_or_else(|e| {
+        eprintln!("{}, starting with an empty {}", e, what);
+        if let Err(e) = fs::rename(path, path.with_extension("json.corrupt")) {
+            eprintln!("Failed to move the old {} aside: {}", what, e);
+        }
+        T::default()
+    })
+}
+
+/// Writes `data` to `path` as JSON.
+pub fn save<T: Serialize>(path: &Path, what: &str, data: &T) -> Result<(), Box<dyn Error>> {
+    // Write to a side file first so a crash mid-write can't corrupt the old one
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_vec_pretty(data)?)
+        .map_err(|e| format!("Failed to write {} {:?}: {}", what, tmp_path, e))?;
+    fs::rename(&tmp_path, path)
+        .map_err(|e| format!("Failed to replace {} {:?}: {}", what, path, e))?;
+    Ok(())
+}
@@ -1,36 +1,92 @@
 use eframe::egui;
-use image::{Rgba, ImageReader, DynamicImage, ImageBuffer, RgbaImage};
+use image::{Rgba, ImageReader, ImageBuffer, RgbaImage};
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     env,
-    sync::mpsc::{self, Sender, Receiver},
+    sync::{mpsc::{self, Sender, Receiver}, Arc},
+    thread,
 };
-use egui::{pos2, Color32, ColorImage, Rect, Vec2, Button, Stroke};
-use std::collections::{HashMap, VecDeque};
-use crate::watcher::FolderWatcher;
-use crate::printer::PdfImageInserter;
-use crate::pdfwrap::{Library, BitmapFormat, PageOrientation, rendering_flags};
+use parking_lot::Mutex;
+use egui::{pos2, Align, Align2, Color32, ColorImage, Pos2, Rect, Sense, Vec2, Button, Stroke};
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::watcher::{self, FolderWatcher, WatchBackend, WatchSettings, WatcherState};
+use crate::printer::{CancelRequests, PdfImageInserter, PrintStatus, SharedLayout, PrintUpdate, OutputSettings, Retention, SharedPrintSettings, ImageColor, Slot, SlotFill, FitMode, DEFAULT_MAX_DPI, decode_upright};
+use crate::printer_wrapper::{PrinterInfo, PaperSize, Orientation, Duplex, ColorMode, PrintQuality, list_printers};
+use crate::journal::Journal;
+use crate::ledger::{Ledger, SharedLedger};
+use crate::profiles::{LayoutProfile, Profiles};
+use crate::upload::{UploadJobs, UploadServer, UploadSettings};
+use crate::pdfwrap::{Library, BitmapFormat, PageBox, PageOrientation, rendering_flags};
 
 
 const MAX_CACHE_SIZE: usize = 13;
 pub const INITIAL_WIDTH: f32 = 900.0;
 pub const INITIAL_HEIGHT: f32 = 600.0;
+const HANDLE_SIZE: f32 = 8.0;
+/// Smallest slot width and height in PDF points, an empty or negative size
+/// would collapse or mirror the photo.
+const MIN_SLOT_SIZE: f32 = 1.0;
+const SLOT_CORNERS: [Align2; 4] = [Align2::LEFT_TOP, Align2::RIGHT_TOP, Align2::LEFT_BOTTOM, Align2::RIGHT_BOTTOM];
+const DEFAULT_SLOT: Slot = Slot {
+    x: 215.0,
+    y: 380.0,
+    width: 360.0,
+    height: 220.0,
+    fit: FitMode::Cover,
+    focus: (0.5, 0.5),
+    rotation: 0.0,
+    page: 0,
+};
 pub struct MyApp {
+    new_images_tx: Sender<String>,
     new_images_rx: Receiver<String>,
     image_list: Vec<String>,
     template_path: Option<String>,
+    pending_template: Option<String>,
     template_image: Option<egui::TextureHandle>,
+    template_page: usize,
+    template_page_count: usize,
+    template_box: PageBox,
+    template_rotation: PageOrientation,
+    pending_page: Option<usize>,
     current_image_texture: Option<egui::TextureHandle>,
     current_image_path: Option<String>,
-    x_coordinate: String,
-    y_coordinate: String,
-    image_width: String,
-    image_height: String,
+    slots: Vec<Slot>,
+    selected_slot: usize,
+    slot_fill: SlotFill,
+    output_settings: OutputSettings,
+    image_color: ImageColor,
+    max_dpi: Option<u32>,
+    print_settings: SharedPrintSettings,
+    ledger: SharedLedger,
+    cancel_requests: CancelRequests,
+    profiles: Profiles,
+    profile_name: String,
+    printers: Vec<PrinterInfo>,
+    /// Set while the printers are being listed in the background.
+    printers_rx: Option<Receiver<Vec<PrinterInfo>>>,
+    printer_uri: String,
     texture_cache: HashMap<String, egui::TextureHandle>,
     cache_order: VecDeque<String>,
     current_index: usize,
     folder_watcher: FolderWatcher,
+    watch_folders: Vec<PathBuf>,
+    watch_settings: WatchSettings,
+    include_patterns: String,
+    exclude_patterns: String,
+    /// Photos that were in the folders before watching started, waiting for
+    /// the operator to import or ignore them.
+    existing_images: Vec<String>,
+    upload_settings: UploadSettings,
+    /// Running while uploads are accepted, into the first watched folder.
+    upload_server: Option<UploadServer>,
+    upload_jobs: UploadJobs,
     image_inserter : Option<Sender<String>>,
+    /// What the running inserter composes new pages with, kept in step with the editor.
+    inserter_layout: Option<SharedLayout>,
+    print_status_tx: Sender<PrintUpdate>,
+    print_status_rx: Receiver<PrintUpdate>,
+    print_status: HashMap<String, PrintStatus>,
     is_testing: bool,
     is_auto_work: bool,
 }
@@ -38,31 +94,264 @@ pub struct MyApp {
 impl MyApp {
     pub fn new() -> Self {
         let (tx, rx) = mpsc::channel();
-        let folder_watcher = FolderWatcher::new(tx);
-        Self {
+        let folder_watcher = FolderWatcher::new(tx.clone());
+        let (print_status_tx, print_status_rx) = mpsc::channel();
+        let mut app = Self {
+            new_images_tx: tx,
             new_images_rx: rx,
             image_list: Vec::new(),
             template_path: None,
+            pending_template: None,
             template_image: None,
+            template_page: 0,
+            template_page_count: 0,
+            template_box: PageBox { left: 0.0, bottom: 0.0, right: 0.0, top: 0.0 },
+            template_rotation: PageOrientation::Normal,
+            pending_page: None,
             current_image_texture: None,
             current_image_path: None,
-            x_coordinate: "215.0".to_string(),
-            y_coordinate: "380.0".to_string(),
-            image_width: "360.0".to_string(),
-            image_height: "220.0".to_string(),
+            slots: vec![DEFAULT_SLOT],
+            selected_slot: 0,
+            slot_fill: SlotFill::default(),
+            output_settings: OutputSettings::default(),
+            image_color: ImageColor::default(),
+            max_dpi: Some(DEFAULT_MAX_DPI),
+            print_settings: SharedPrintSettings::default(),
+            ledger: Arc::new(Mutex::new(Ledger::open_or_reset(Ledger::default_path()))),
+            cancel_requests: CancelRequests::default(),
+            profiles: Profiles::open_or_reset(Profiles::default_path()),
+            profile_name: String::new(),
+            printers: Vec::new(),
+            printers_rx: None,
+            printer_uri: String::new(),
             texture_cache: HashMap::new(),
             cache_order: VecDeque::new(),
             current_index: 0,
             folder_watcher: folder_watcher,
+            watch_folders: Vec::new(),
+            watch_settings: WatchSettings::default(),
+            include_patterns: String::new(),
+            exclude_patterns: String::new(),
+            existing_images: Vec::new(),
+            upload_settings: UploadSettings::default(),
+            upload_server: None,
+            upload_jobs: UploadJobs::default(),
             image_inserter : None,
+            inserter_layout: None,
+            print_status_tx,
+            print_status_rx,
+            print_status: HashMap::new(),
             is_testing: false,
             is_auto_work: false,
+        };
+        app.refresh_printers();
+        if let Some(profile) = app.profiles.last_used().cloned() {
+            app.apply_profile(&profile);
+        }
+        app.resume_unfinished_jobs();
+        app
+    }
+
+    /// Lists the printers on a background thread, asking an unreachable network
+    /// printer for its media can take long enough to freeze the window.
+    fn refresh_printers(&mut self) {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let printers = list_printers().unwrap_or_else(|e| {
+                eprintln!("Failed to list printers: {}", e);
+                Vec::new()
+            });
+            // The window may already be closed
+            let _ = tx.send(printers);
+        });
+        self.printers_rx = Some(rx);
+    }
+
+    /// Restarts the inserter right away when the previous run left jobs in the
+    /// journal. Those print with the layout they were queued with, the editor
+    /// keeps the restored profile for new photos.
+    fn resume_unfinished_jobs(&mut self) {
+        let journaled_layout = match Journal::open(Journal::default_path()) {
+            Ok(journal) => match journal.entries().last() {
+                Some(entry) => entry.layout.clone(),
+                None => return,
+            },
+            Err(e) => {
+                eprintln!("Couldn't read the print journal: {}", e);
+                return;
+            }
+        };
+
+        // The profile's template is only loaded with the first frame
+        let layout = match self.pending_template.clone().or_else(|| self.template_path.clone()) {
+            Some(template_path) => self.current_layout(template_path),
+            None => journaled_layout,
+        };
+        let shared_layout = Arc::new(Mutex::new(layout));
+        match PdfImageInserter::spawn(
+            shared_layout.clone(),
+            self.print_settings.clone(),
+            self.ledger.clone(),
+            self.cancel_requests.clone(),
+            self.print_status_tx.clone(),
+        ) {
+            Ok(tx) => {
+                self.image_inserter = Some(tx);
+                self.inserter_layout = Some(shared_layout);
+            }
+            Err(e) => eprintln!("Couldn't create an Inserter: {}", e),
+        }
+    }
+
+    fn restart_watcher(&mut self) {
+        let split = |patterns: &str| patterns.split(',').map(str::trim).filter(|p| !p.is_empty()).map(String::from).collect();
+        self.watch_settings.include = split(&self.include_patterns);
+        self.watch_settings.exclude = split(&self.exclude_patterns);
+        // With no folders left this just stops the old watcher
+        let result = self.folder_watcher.spawn_watcher(&self.watch_folders, &self.watch_settings);
+        self.offer_existing(result);
+        match (self.watch_folders.first(), &self.upload_server) {
+            (Some(folder), Some(server)) => server.set_upload_dir(folder.clone()),
+            (None, Some(_)) => self.upload_server = None,
+            _ => {}
+        }
+    }
+
+    /// Starts or stops taking photo uploads over HTTP, which land in the first
+    /// watched folder and come in like the watcher's photos.
+    fn accept_uploads(&mut self, accept: bool) {
+        self.upload_server = None;
+        let Some(folder) = self.watch_folders.first().filter(|_| accept) else { return };
+        match UploadServer::spawn(
+            &self.upload_settings,
+            folder.clone(),
+            self.new_images_tx.clone(),
+            self.folder_watcher.claimed(),
+            self.upload_jobs.clone(),
+        ) {
+            Ok(server) => self.upload_server = Some(server),
+            Err(e) => eprintln!("Failed to accept uploads on port {}: {}", self.upload_settings.port, e),
+        }
+    }
+
+    /// Lists the photos found when watching (re)started that aren't shown yet.
+    fn offer_existing(&mut self, result: notify::Result<Vec<String>>) {
+        match result {
+            Ok(existing) => self.existing_images = existing.into_iter().filter(|p| !self.image_list.contains(p)).collect(),
+            Err(e) => eprintln!("Failed to watch folder: {:?}", e),
+        }
+    }
+
+    /// Adds the photos found when watching started to the list, oldest first,
+    /// and prints those that haven't been queued before if asked to.
+    fn import_existing(&mut self, ctx: &egui::Context, print: bool) {
+        // Photos of unfinished jobs get printed when the inserter resumes them
+        let journaled: HashSet<String> = match Journal::open(Journal::default_path()) {
+            Ok(journal) if print => journal.entries().iter().flat_map(|e| e.image_paths.iter().cloned()).collect(),
+            _ => HashSet::new(),
+        };
+        for path in std::mem::take(&mut self.existing_images) {
+            if !self.image_list.contains(&path) {
+                self.image_list.push(path.clone());
+            }
+            if print && !self.print_status.contains_key(&path) && !journaled.contains(&path) {
+                if let Err(e) = self.queue_print(&path) {
+                    eprintln!("{}", e);
+                    break;
+                }
+            }
+        }
+        self.update_cache(ctx);
+        self.current_index = self.image_list.len().saturating_sub(1);
+    }
+
+    /// Prints the photo even though the ledger says it was printed before.
+    fn reprint(&mut self, path: &str) {
+        if let Err(e) = self.ledger.lock().forget(path) {
+            eprintln!("Failed to update print ledger: {}", e);
+            return;
+        }
+        if let Err(e) = self.queue_print(path) {
+            eprintln!("{}", e);
+        }
+    }
+
+    /// Hands the photo to the inserter, starting it first if needed. It prints
+    /// with the layout the editor shows right now.
+    fn queue_print(&mut self, path: &str) -> Result<(), String> {
+        let template_path = self.template_path.clone().ok_or("No template is selected")?;
+        let layout = self.current_layout(template_path);
+        match &self.inserter_layout {
+            Some(shared) => *shared.lock() = layout,
+            None => {
+                let shared_layout = Arc::new(Mutex::new(layout));
+                let inserter_tx = PdfImageInserter::spawn(
+                    shared_layout.clone(),
+                    self.print_settings.clone(),
+                    self.ledger.clone(),
+                    self.cancel_requests.clone(),
+                    self.print_status_tx.clone(),
+                )
+                .map_err(|e| format!("Couldn't create an Inserter: {}", e))?;
+                self.image_inserter = Some(inserter_tx);
+                self.inserter_layout = Some(shared_layout);
+            }
+        }
+        self.image_inserter
+            .as_ref()
+            .unwrap()
+            .send(path.to_string())
+            .map_err(|e| format!("Failed to send print job: {}", e))?;
+        self.print_status.insert(path.to_string(), PrintStatus::Queued);
+        Ok(())
+    }
+
+    fn apply_layout(&mut self, layout: &PdfImageInserter) {
+        self.slots = layout.slots().to_vec();
+        self.selected_slot = 0;
+        self.slot_fill = layout.fill();
+        self.output_settings = layout.output().clone();
+        self.image_color = layout.image_color();
+        self.max_dpi = layout.max_dpi();
+        self.pending_template = Some(layout.template_path().to_string());
+    }
+
+    fn apply_profile(&mut self, profile: &LayoutProfile) {
+        self.apply_layout(&profile.layout);
+        *self.print_settings.lock() = profile.print.clone();
+        self.profile_name = profile.name.clone();
+    }
+
+    fn save_profile(&mut self) {
+        let Some(template_path) = self.template_path.clone() else {
+            eprintln!("Pick a template before saving a profile");
+            return;
+        };
+        let profile = LayoutProfile {
+            name: self.profile_name.trim().to_string(),
+            layout: self.current_layout(template_path),
+            print: self.print_settings.lock().clone(),
+        };
+        if let Err(e) = self.profiles.save_profile(profile) {
+            eprintln!("Failed to save profile: {}", e);
         }
     }
 
-    fn load_template_pdf(&mut self, ctx: &egui::Context, pdf_path: &str) {
-        if let Some(image) = render_pdf_page_to_image(pdf_path) {
+    fn current_layout(&self, template_path: String) -> PdfImageInserter {
+        PdfImageInserter::new(template_path, self.slots.clone())
+            .with_fill(self.slot_fill)
+            .with_output(self.output_settings.clone())
+            .with_image_color(self.image_color)
+            .with_max_dpi(self.max_dpi)
+    }
+
+    fn load_template_pdf(&mut self, ctx: &egui::Context, pdf_path: &str, page: usize) {
+        if let Some(RenderedPage { image, page_count, page_box, rotation }) = render_pdf_page_to_image(pdf_path, page) {
             self.template_path = Some(pdf_path.to_string());
+            self.template_page = page;
+            self.template_page_count = page_count;
+            self.template_box = page_box;
+            self.template_rotation = rotation;
             let size = [image.width() as usize, image.height() as usize];
             let pixels = image.to_vec();
             let color_image = egui::ColorImage::from_rgba_unmultiplied(size, &pixels);
@@ -77,7 +366,7 @@ impl MyApp {
 
         for path in &recent_paths {
             if !self.texture_cache.contains_key(path) {
-                if let Ok(texture) = load_image_from_path(path, ctx) {
+                if let Ok(texture) = load_image_from_path(path, self.image_color, ctx) {
                     self.texture_cache.insert(path.clone(), texture);
                     self.cache_order.push_back(path.clone());
                     if self.cache_order.len() > MAX_CACHE_SIZE {
@@ -101,12 +390,21 @@ impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let is_focused = ctx.input(|i| i.raw.focused);
         let mut should_repaint = false;
+        if let Some(path) = self.pending_template.take() {
+            self.load_template_pdf(ctx, &path, 0);
+            should_repaint = true;
+        }
+        if let (Some(page), Some(path)) = (self.pending_page.take(), self.template_path.clone()) {
+            if page != self.template_page {
+                self.load_template_pdf(ctx, &path, page);
+                should_repaint = true;
+            }
+        }
         if self.is_testing {
             let current_dir = env::current_dir().unwrap();
-            self.load_template_pdf(ctx, "Berlin.pdf");
-            if let Err(e) = self.folder_watcher.spawn_watcher(current_dir.join("folder_to_monitor")) {
-                eprintln!("Failed to spawn watcher: {:?}", e);
-            }
+            self.load_template_pdf(ctx, "Berlin.pdf", 0);
+            self.watch_folders = vec![current_dir.join("folder_to_monitor")];
+            self.restart_watcher();
             self.image_list.push(current_dir.join("folder_to_monitor").join("test_image.jpeg").to_string_lossy().to_string());
             self.update_cache(ctx);
             self.is_testing = false;
@@ -123,26 +421,30 @@ impl eframe::App for MyApp {
             println!("Image List: {:?}", self.image_list);
 
             if self.is_auto_work {
-
-                if self.image_inserter.is_none() {
-                    let x: f32 = self.x_coordinate.parse().unwrap_or(215.0);
-                    let y: f32 = self.y_coordinate.parse().unwrap_or(380.0);
-                    let w: f32 = self.image_width.parse().unwrap_or(360.0);
-                    let h: f32 = self.image_height.parse().unwrap_or(220.0);
-                    
-                    let inserter_tx = match PdfImageInserter::new_and_spawn(self.template_path.as_ref().expect("No Template is Selected").clone(), x, y, w, h) {
-                        Ok(tx) => tx,
-                        Err(e) => {
-                            eprintln!("Couldn't create an Inserter: {}", e);
-                            return;
-                        }
-                    };
-                    self.image_inserter = Some(inserter_tx);
+                if let Err(e) = self.queue_print(&path) {
+                    eprintln!("{}", e);
                 }
-                if let Err(e) = self.image_inserter.as_ref().unwrap().send(path.clone()) {
-                    eprintln!("Failed to send print job: {}", e);
+            }
+            should_repaint = true;
+        }
+
+
+        if let Some(rx) = &self.printers_rx {
+            match rx.try_recv() {
+                Ok(printers) => {
+                    self.printers = printers;
+                    self.printers_rx = None;
+                    should_repaint = true;
                 }
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => self.printers_rx = None,
             }
+        }
+
+        while let Ok(update) = self.print_status_rx.try_recv() {
+            println!("Print status of {}: {}", update.image_path, update.status.label());
+            self.upload_jobs.update(&update);
+            self.print_status.insert(update.image_path, update.status);
             should_repaint = true;
         }
 
@@ -153,44 +455,59 @@ impl eframe::App for MyApp {
                 if ui.button("Layout").clicked() {
                     if let Some(path) = rfd::FileDialog::new()
                         .add_filter("PDF files", &["pdf"]).pick_file() {
-                        self.load_template_pdf(ctx, &path.to_string_lossy());
+                        self.load_template_pdf(ctx, &path.to_string_lossy(), 0);
                         should_repaint = true;
                     }
                 }
                 if ui.button("Folder").clicked() {
                     if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                        if let Err(e) = self.folder_watcher.spawn_watcher(path) {
-                            eprintln!("Failed to watch folder: {:?}", e);
+                        self.watch_folders = vec![path];
+                        self.restart_watcher();
+                    }
+                }
+                if ui.button("Add folder").clicked() {
+                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                        if !self.watch_folders.contains(&path) {
+                            self.watch_folders.push(path);
+                            self.restart_watcher();
                         }
                     }
                 }
-                if ui.button("Print").clicked() {
-                    if let (Some(temp), Some(img)) = (&self.template_path, &self.current_image_path) {
-
-                        
-
-                        if self.image_inserter.is_none() {
-                            let x: f32 = self.x_coordinate.parse().unwrap_or(215.0);
-                            let y: f32 = self.y_coordinate.parse().unwrap_or(380.0);
-                            let w: f32 = self.image_width.parse().unwrap_or(360.0);
-                            let h: f32 = self.image_height.parse().unwrap_or(220.0);
-
-                            let inserter_tx = match PdfImageInserter::new_and_spawn(temp.clone(), x, y, w, h) {
-                                Ok(tx) => tx,
-                                Err(e) => {
-                                    eprintln!("Couldn't create an Inserter: {}", e);
-                                    return;
-                                }
-                            };
-                            self.image_inserter = Some(inserter_tx);
+                if ui.button("Print").clicked() && self.template_path.is_some() {
+                    if let Some(img) = self.current_image_path.clone() {
+                        if let Err(e) = self.queue_print(&img) {
+                            eprintln!("{}", e);
                         }
+                    }
+                }
 
-                        if let Err(e) = self.image_inserter.as_ref().unwrap().send(img.clone()) {
-                            eprintln!("Failed to send print job: {}", e);
+                ui.separator();
+                egui::ComboBox::from_label("Profile")
+                    .selected_text(self.profile_name.clone())
+                    .show_ui(ui, |ui| {
+                        let mut chosen = None;
+                        for profile in self.profiles.profiles() {
+                            if ui.selectable_label(profile.name == self.profile_name, &profile.name).clicked() {
+                                chosen = Some(profile.clone());
+                            }
+                        }
+                        if let Some(profile) = chosen {
+                            self.apply_profile(&profile);
+                            if let Err(e) = self.profiles.set_last_used(&profile.name) {
+                                eprintln!("Failed to save profiles: {}", e);
+                            }
+                            should_repaint = true;
                         }
-                        
+                    });
+                ui.add(egui::TextEdit::singleline(&mut self.profile_name).hint_text("Profile name").desired_width(120.0));
+                let has_name = !self.profile_name.trim().is_empty();
+                if ui.add_enabled(has_name, Button::new("Save")).clicked() {
+                    self.save_profile();
+                }
+                if ui.add_enabled(self.profiles.get(self.profile_name.trim()).is_some(), Button::new("Delete")).clicked() {
+                    if let Err(e) = self.profiles.remove(self.profile_name.trim()) {
+                        eprintln!("Failed to delete profile: {}", e);
                     }
-
                 }
             });
         });
@@ -202,7 +519,7 @@ impl eframe::App for MyApp {
                     self.current_image_path = Some(path.clone());
                     should_repaint = true;
                 }
-                else if let Ok(img) = load_image_from_path(&path, ctx) {
+                else if let Ok(img) = load_image_from_path(&path, self.image_color, ctx) {
                     self.current_image_texture = Some(img);
                     self.current_image_path = Some(path.clone());
                     should_repaint = true;
@@ -241,41 +558,331 @@ impl eframe::App for MyApp {
                         //Can implement ImageLoader for egui 
                         draw_full_image(ui, template);
                     }
-                    if let (Some(template), Some(current)) = (&self.template_image, &self.current_image_texture) {
-                        
-
+                    if let Some(template) = &self.template_image {
                         let aspect_ratio = template.size()[0] as f32 / template.size()[1] as f32;
-                        
                         let template_w : f32 = f32::min(aspect_ratio * height, left_width);
-                        let template_h : f32 = f32::min(left_width / aspect_ratio, height);
-
-                        let top_left = ui.min_rect().left_top();
-                        let x= top_left[0] + 0.360 * template_w;
-                        let y = top_left[1] + 0.30 * template_h;
-                        let w = template_w * 0.605;
-                        let h = template_h * 0.25;
-
-                        
-                        
-                        ui.painter().image(
-                            current.id(),
-                            Rect::from_min_size(pos2(x, y), Vec2::new(w, h)),
-                            Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
-                            Color32::WHITE,
-                        );
+                        let page = PageTransform::new(ui.min_rect().left_top(), template_w, self.template_box, self.template_rotation);
+                        let current = self
+                            .current_image_texture
+                            .as_ref()
+                            .map(|t| (t.id(), t.size()[0] as f32 / t.size()[1] as f32));
+
+                        for i in 0..self.slots.len() {
+                            if self.slots[i].page != self.template_page {
+                                continue;
+                            }
+                            let mut rect = page.slot_rect(&self.slots[i]);
+                            let mut moved = false;
+
+                            let body = ui
+                                .interact(rect, ui.id().with(("slot", i)), Sense::click_and_drag())
+                                .on_hover_cursor(egui::CursorIcon::Move);
+                            if body.clicked() || body.drag_started() {
+                                self.selected_slot = i;
+                                should_repaint = true;
+                            }
+                            if body.dragged() {
+                                rect = rect.translate(body.drag_delta());
+                                moved = true;
+                            }
+                            if i == self.selected_slot {
+                                for (n, corner) in SLOT_CORNERS.into_iter().enumerate() {
+                                    let handle_rect = Rect::from_center_size(corner.pos_in_rect(&rect), Vec2::splat(HANDLE_SIZE));
+                                    let cursor = if corner == Align2::LEFT_TOP || corner == Align2::RIGHT_BOTTOM {
+                                        egui::CursorIcon::ResizeNwSe
+                                    } else {
+                                        egui::CursorIcon::ResizeNeSw
+                                    };
+                                    let handle = ui
+                                        .interact(handle_rect, ui.id().with(("slot_handle", i, n)), Sense::drag())
+                                        .on_hover_cursor(cursor);
+                                    if handle.dragged() {
+                                        let delta = handle.drag_delta();
+                                        match corner.x() {
+                                            Align::Min => rect.min.x = (rect.min.x + delta.x).min(rect.max.x - 1.0),
+                                            _ => rect.max.x = (rect.max.x + delta.x).max(rect.min.x + 1.0),
+                                        }
+                                        match corner.y() {
+                                            Align::Min => rect.min.y = (rect.min.y + delta.y).min(rect.max.y - 1.0),
+                                            _ => rect.max.y = (rect.max.y + delta.y).max(rect.min.y + 1.0),
+                                        }
+                                        moved = true;
+                                    }
+                                }
+                            }
+                            if moved {
+                                page.set_slot_rect(&mut self.slots[i], rect);
+                                should_repaint = true;
+                            }
+
+                            let slot = &self.slots[i];
+                            let rect = page.slot_rect(slot);
+                            // Successive slots get later photos, only the current one is known here
+                            if let Some((texture, aspect)) = current.filter(|_| i == 0 || self.slot_fill == SlotFill::Repeat) {
+                                paint_transformed(&ui.painter().with_clip_rect(rect), texture, slot.image_matrix(aspect), |x, y| page.to_screen(x, y));
+                            }
+                            if i == self.selected_slot {
+                                ui.painter().rect_stroke(rect, 0.0, Stroke::new(2.0, Color32::LIGHT_BLUE), egui::StrokeKind::Inside);
+                                for corner in SLOT_CORNERS {
+                                    let handle_rect = Rect::from_center_size(corner.pos_in_rect(&rect), Vec2::splat(HANDLE_SIZE));
+                                    ui.painter().rect_filled(handle_rect, 0.0, Color32::LIGHT_BLUE);
+                                }
+                            } else {
+                                ui.painter().rect_stroke(rect, 0.0, Stroke::new(1.0, Color32::GRAY), egui::StrokeKind::Inside);
+                            }
+                        }
                     }
                 });
                 ui.vertical(|ui| {
                     ui.set_width(right_width); 
                     ui.add_space(20.0);
-                    ui.label("X coordinate");
-                    should_repaint |= ui.text_edit_singleline(&mut self.x_coordinate).changed();
-                    ui.label("Y coordinate");
-                    should_repaint |= ui.text_edit_singleline(&mut self.y_coordinate).changed();
-                    ui.label("Image width");
-                    should_repaint |= ui.text_edit_singleline(&mut self.image_width).changed();
-                    ui.label("Image height");
-                    should_repaint |= ui.text_edit_singleline(&mut self.image_height).changed();
+                    if self.template_page_count > 1 {
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(self.template_page > 0, Button::new("<")).clicked() {
+                                self.pending_page = Some(self.template_page - 1);
+                            }
+                            ui.label(format!("Page {} / {}", self.template_page + 1, self.template_page_count));
+                            if ui.add_enabled(self.template_page + 1 < self.template_page_count, Button::new(">")).clicked() {
+                                self.pending_page = Some(self.template_page + 1);
+                            }
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Slot");
+                        for i in 0..self.slots.len() {
+                            if ui.selectable_value(&mut self.selected_slot, i, (i + 1).to_string()).changed() {
+                                self.pending_page = Some(self.slots[i].page);
+                                should_repaint = true;
+                            }
+                        }
+                        if ui.button("+").clicked() {
+                            let slot = self.slots.get(self.selected_slot).copied().unwrap_or(DEFAULT_SLOT);
+                            self.slots.push(slot);
+                            self.selected_slot = self.slots.len() - 1;
+                            should_repaint = true;
+                        }
+                        if ui.add_enabled(self.slots.len() > 1, Button::new("-")).clicked() {
+                            self.slots.remove(self.selected_slot);
+                            self.selected_slot = self.selected_slot.min(self.slots.len() - 1);
+                            should_repaint = true;
+                        }
+                    });
+                    if let Some(slot) = self.slots.get_mut(self.selected_slot) {
+                        for (label, value, min) in [
+                            ("X coordinate", &mut slot.x, f32::MIN),
+                            ("Y coordinate", &mut slot.y, f32::MIN),
+                            ("Image width", &mut slot.width, MIN_SLOT_SIZE),
+                            ("Image height", &mut slot.height, MIN_SLOT_SIZE),
+                        ] {
+                            ui.horizontal(|ui| {
+                                ui.label(label);
+                                should_repaint |= ui.add(egui::DragValue::new(value).range(min..=f32::MAX).speed(1.0).suffix(" pt")).changed();
+                            });
+                        }
+                        if self.template_page_count > 1 {
+                            ui.horizontal(|ui| {
+                                ui.label("Page");
+                                let mut page = slot.page + 1;
+                                if ui.add(egui::DragValue::new(&mut page).range(1..=self.template_page_count)).changed() {
+                                    slot.page = page - 1;
+                                    self.pending_page = Some(slot.page);
+                                }
+                            });
+                        }
+                        should_repaint |= enum_combo(ui, "Fit", &mut slot.fit, &FitMode::ALL, FitMode::label);
+                        ui.horizontal(|ui| {
+                            ui.label("Rotation");
+                            for angle in [0.0, 90.0, 180.0, 270.0] {
+                                should_repaint |= ui.selectable_value(&mut slot.rotation, angle, format!("{}°", angle)).changed();
+                            }
+                            should_repaint |= ui.add(egui::DragValue::new(&mut slot.rotation).range(-360.0..=360.0).speed(0.5).suffix("°")).changed();
+                        });
+                        if slot.fit == FitMode::Focus {
+                            ui.horizontal(|ui| {
+                                ui.label("Focal point");
+                                should_repaint |= ui.add(egui::DragValue::new(&mut slot.focus.0).range(0.0..=1.0).speed(0.01)).changed();
+                                should_repaint |= ui.add(egui::DragValue::new(&mut slot.focus.1).range(0.0..=1.0).speed(0.01)).changed();
+                            });
+                        }
+                    }
+                    should_repaint |= enum_combo(ui, "Slots", &mut self.slot_fill, &SlotFill::ALL, SlotFill::label);
+
+                    ui.separator();
+                    let mut options = self.print_settings.lock().options.clone();
+                    let mut options_changed = false;
+                    ui.horizontal(|ui| {
+                        ui.label("Copies");
+                        options_changed |= ui.add(egui::DragValue::new(&mut options.copies).range(1..=99)).changed();
+                    });
+                    options_changed |= enum_combo(ui, "Paper size", &mut options.paper_size, &PaperSize::ALL, PaperSize::label);
+                    options_changed |= enum_combo(ui, "Orientation", &mut options.orientation, &Orientation::ALL, Orientation::label);
+                    options_changed |= enum_combo(ui, "Duplex", &mut options.duplex, &Duplex::ALL, Duplex::label);
+                    options_changed |= enum_combo(ui, "Color", &mut options.color_mode, &ColorMode::ALL, ColorMode::label);
+                    options_changed |= enum_combo(ui, "Quality", &mut options.quality, &PrintQuality::ALL, PrintQuality::label);
+                    options_changed |= ui.checkbox(&mut options.fit_to_page, "Fit to page").changed();
+                    if options_changed {
+                        self.print_settings.lock().options = options;
+                    }
+
+                    ui.separator();
+                    let selected = self.print_settings.lock().printer_name.clone();
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_label("Printer")
+                            .selected_text(selected.clone().unwrap_or_else(|| "System default".to_string()))
+                            .show_ui(ui, |ui| {
+                                let mut choice = selected.clone();
+                                ui.selectable_value(&mut choice, None, "System default");
+                                for printer in &self.printers {
+                                    let mut label = printer.name.clone();
+                                    if printer.is_default {
+                                        label.push_str(" (default)");
+                                    }
+                                    if !printer.is_online {
+                                        label.push_str(" (offline)");
+                                    }
+                                    ui.selectable_value(&mut choice, Some(printer.name.clone()), label);
+                                }
+                                if choice != selected {
+                                    self.print_settings.lock().printer_name = choice;
+                                }
+                            });
+                        if self.printers_rx.is_some() {
+                            ui.spinner();
+                        } else if ui.button("Refresh").clicked() {
+                            self.refresh_printers();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut self.printer_uri).hint_text("ipp://printer.local/ipp/print"));
+                        if ui.button("Use").clicked() && !self.printer_uri.trim().is_empty() {
+                            self.print_settings.lock().printer_name = Some(self.printer_uri.trim().to_string());
+                        }
+                    });
+                    if let Some(info) = self.printers.iter().find(|p| Some(&p.name) == selected.as_ref()) {
+                        ui.label(if info.is_online { "Printer is online" } else { "Printer is offline" });
+                        if !info.media.is_empty() {
+                            ui.collapsing("Supported media", |ui| {
+                                for media in &info.media {
+                                    ui.label(media);
+                                }
+                            });
+                        }
+                    }
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        if ui.button("Output folder").clicked() {
+                            if let Some(dir) = rfd::FileDialog::new().set_directory(&self.output_settings.dir).pick_folder() {
+                                self.output_settings.dir = dir;
+                            }
+                        }
+                        ui.label(self.output_settings.dir.to_string_lossy());
+                    });
+                    enum_combo(ui, "After printing", &mut self.output_settings.retention, &Retention::ALL, Retention::label);
+                    if enum_combo(ui, "Image color", &mut self.image_color, &ImageColor::ALL, ImageColor::label) {
+                        // Previews are rendered in the embedded color space, rebuild them
+                        self.texture_cache.clear();
+                        self.cache_order.clear();
+                        self.current_image_path = None;
+                        should_repaint = true;
+                    }
+                    ui.horizontal(|ui| {
+                        let mut downsample = self.max_dpi.is_some();
+                        if ui.checkbox(&mut downsample, "Downsample to").changed() {
+                            self.max_dpi = downsample.then_some(DEFAULT_MAX_DPI);
+                        }
+                        if let Some(dpi) = &mut self.max_dpi {
+                            ui.add(egui::DragValue::new(dpi).range(72..=1200).suffix(" dpi"));
+                        }
+                    });
+
+                    ui.separator();
+                    let mut watch_changed = false;
+                    let mut removed = None;
+                    let statuses = self.folder_watcher.statuses();
+                    for (i, folder) in self.watch_folders.iter().enumerate() {
+                        let status = statuses.iter().find(|s| s.folder == watcher::absolute(folder));
+                        ui.horizontal(|ui| {
+                            if ui.small_button("x").clicked() {
+                                removed = Some(i);
+                            }
+                            ui.label(folder.to_string_lossy());
+                            if status.is_some_and(|s| s.polling) {
+                                ui.weak("polling");
+                            }
+                        });
+                        if let Some(error) = status.and_then(|s| s.error.as_ref()) {
+                            ui.colored_label(Color32::LIGHT_RED, format!("Unreachable: {}", error));
+                        }
+                    }
+                    if let Some(i) = removed {
+                        self.watch_folders.remove(i);
+                        watch_changed = true;
+                    }
+                    watch_changed |= ui.checkbox(&mut self.watch_settings.recursive, "Include subfolders").changed();
+                    watch_changed |= enum_combo(ui, "Watch with", &mut self.watch_settings.backend, &WatchBackend::ALL, WatchBackend::label);
+                    if self.watch_settings.backend != WatchBackend::Events {
+                        ui.horizontal(|ui| {
+                            ui.label("Poll every");
+                            let edit = ui.add(egui::DragValue::new(&mut self.watch_settings.poll_interval_secs).range(1..=600).suffix(" s"));
+                            watch_changed |= edit.drag_stopped() || edit.lost_focus();
+                        });
+                    }
+                    for (label, patterns) in [("Only", &mut self.include_patterns), ("Skip", &mut self.exclude_patterns)] {
+                        ui.horizontal(|ui| {
+                            ui.label(label);
+                            let edit = ui.add(egui::TextEdit::singleline(patterns).hint_text("*.jpg, DCIM/*"));
+                            watch_changed |= edit.lost_focus();
+                        });
+                    }
+                    if watch_changed {
+                        self.restart_watcher();
+                    }
+                    if !self.watch_folders.is_empty() {
+                        let state = self.folder_watcher.state();
+                        ui.horizontal(|ui| {
+                            match &state {
+                                WatcherState::Error(_) => ui.colored_label(Color32::LIGHT_RED, format!("Watcher: {}", state.label())),
+                                _ => ui.label(format!("Watcher: {}", state.label())),
+                            };
+                            if state == WatcherState::Paused {
+                                if ui.button("Resume").clicked() {
+                                    let result = self.folder_watcher.restart();
+                                    self.offer_existing(result);
+                                }
+                            } else if ui.button("Pause").clicked() {
+                                self.folder_watcher.stop();
+                            }
+                        });
+                    }
+                    if !self.existing_images.is_empty() {
+                        ui.label(format!("{} photo(s) already in the folder", self.existing_images.len()));
+                        ui.horizontal(|ui| {
+                            if ui.button("Import").clicked() {
+                                self.import_existing(ctx, false);
+                                should_repaint = true;
+                            }
+                            if ui.button("Import and print").clicked() {
+                                self.import_existing(ctx, true);
+                                should_repaint = true;
+                            }
+                            if ui.button("Ignore").clicked() {
+                                self.existing_images.clear();
+                            }
+                        });
+                    }
+                    if !self.watch_folders.is_empty() {
+                        ui.horizontal(|ui| {
+                            let mut accept = self.upload_server.is_some();
+                            if ui.checkbox(&mut accept, "Accept uploads").changed() {
+                                self.accept_uploads(accept);
+                            }
+                            match &self.upload_server {
+                                Some(server) => ui.weak(format!("on port {}", server.port())),
+                                None => ui.add(egui::DragValue::new(&mut self.upload_settings.port).range(1..=65535).prefix("port ")),
+                            };
+                        });
+                    }
 
                     let auto_work_button = if self.is_auto_work {
                         Button::new("AutoWork").stroke(Stroke::new(1.5, Color32::LIGHT_BLUE))
@@ -308,6 +915,36 @@ impl eframe::App for MyApp {
                     });
                     ui.separator();
                     ui.label(format!("Index: {}", self.current_index));
+                    let mut reprint = None;
+                    if let Some((path, status)) = self.current_image_path.as_ref().and_then(|p| Some((p, self.print_status.get(p)?))) {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Print status: {}", status.label()));
+                            if (*status == PrintStatus::AlreadyPrinted || status.is_failed() || status.is_unconfirmed()) && ui.button("Reprint").clicked() {
+                                reprint = Some(path.clone());
+                            }
+                            if matches!(status, PrintStatus::Retrying { .. }) && ui.button("Cancel").clicked() {
+                                self.cancel_requests.lock().insert(path.clone());
+                            }
+                        });
+                    }
+
+                    let failed: Vec<(&String, &PrintStatus)> = self.print_status.iter().filter(|(_, s)| s.is_failed()).collect();
+                    if !failed.is_empty() {
+                        ui.separator();
+                        ui.colored_label(Color32::LIGHT_RED, format!("Failed prints ({})", failed.len()));
+                        for (path, status) in failed {
+                            let name = Path::new(path).file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+                            ui.horizontal(|ui| {
+                                ui.colored_label(Color32::LIGHT_RED, format!("{}: {}", name, status.label()));
+                                if ui.small_button("Reprint").clicked() {
+                                    reprint = Some(path.clone());
+                                }
+                            });
+                        }
+                    }
+                    if let Some(path) = reprint {
+                        self.reprint(&path);
+                    }
                 });
             });
         });
@@ -321,11 +958,9 @@ impl eframe::App for MyApp {
     }
 }
 
-fn load_image_from_path(path: &str, ctx: &egui::Context) -> Result<egui::TextureHandle, String> {
-    let img = ImageReader::open(path)
-        .map_err(|e| format!("Failed to open image: {}", e))?
-        .decode()
-        .map_err(|e| format!("Failed to decode image: {}", e))?;
+fn load_image_from_path(path: &str, image_color: ImageColor, ctx: &egui::Context) -> Result<egui::TextureHandle, String> {
+    let reader = ImageReader::open(path).map_err(|e| format!("Failed to open image: {}", e))?;
+    let (img, _) = decode_upright(reader).map_err(|e| format!("Failed to decode image: {}", e))?;
 
     let max_dim = 200u32;
     let (orig_w, orig_h) = (img.width(), img.height());
@@ -336,31 +971,42 @@ fn load_image_from_path(path: &str, ctx: &egui::Context) -> Result<egui::Texture
     };
     let resized = img.resize_exact(new_w, new_h, image::imageops::FilterType::Lanczos3);
 
-    let gray_dynamic: DynamicImage = resized.grayscale();
-    let gray_buf = gray_dynamic.into_luma8();
-    let (w, h) = gray_buf.dimensions();
-    let size = [w as usize, h as usize];
-    let luma_pixels = gray_buf.into_raw();
-    let mut rgba_pixels = Vec::with_capacity(w as usize * h as usize * 4);
-    for &l in &luma_pixels {
-        rgba_pixels.extend_from_slice(&[l, l, l, 255]);
-    }
-    let color_image = ColorImage::from_rgba_unmultiplied(size, &rgba_pixels);
+    let preview = image_color.preview(&resized).into_rgba8();
+    let size = [preview.width() as usize, preview.height() as usize];
+    let color_image = ColorImage::from_rgba_unmultiplied(size, preview.as_raw());
     Ok(ctx.load_texture(path, color_image, egui::TextureOptions::default()))
 }
 
 
-fn render_pdf_page_to_image(pdf_path: &str) -> Option<RgbaImage> {
+struct RenderedPage {
+    image: RgbaImage,
+    page_count: usize,
+    page_box: PageBox,
+    rotation: PageOrientation,
+}
+
+/// Renders one page of the template, along with what the preview needs to map
+/// PDF coordinates onto it.
+fn render_pdf_page_to_image(pdf_path: &str, page_index: usize) -> Option<RenderedPage> {
 
     let library = Library::init_library()?;
 
     let path = Path::new(pdf_path);
 
     let document = library.load_document(&path, None).ok()?;
-    let page = library.load_page(&document, 0).ok()?;
+    let page_count = library.get_page_count(&document);
+    let page = library.load_page(&document, page_index).ok()?;
 
     let width = library.get_page_width(&page).round() as usize;
     let height = library.get_page_height(&page).round() as usize;
+    let rotation = library.get_page_rotation(&page);
+    let page_box = library.get_page_bounding_box(&page).unwrap_or_else(|| {
+        let (w, h) = match rotation {
+            PageOrientation::Normal | PageOrientation::Flip => (width, height),
+            PageOrientation::Clockwise | PageOrientation::CounterClockwise => (height, width),
+        };
+        PageBox { left: 0.0, bottom: 0.0, right: w as f32, top: h as f32 }
+    });
 
     let format = BitmapFormat::BGRA;
     let stride = width * format.bytes_per_pixel();
@@ -399,7 +1045,103 @@ fn render_pdf_page_to_image(pdf_path: &str) -> Option<RgbaImage> {
     
     let image: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_raw(width as u32, height as u32, rgba_data).unwrap();
     if image.is_empty() { eprintln!("Failed to render PDF page."); return None; }
-    Some(image)
+    Some(RenderedPage { image, page_count, page_box, rotation })
+}
+
+fn enum_combo<T: Copy + PartialEq>(ui: &mut egui::Ui, label: &str, value: &mut T, all: &[T], name: fn(&T) -> &'static str) -> bool {
+    let mut changed = false;
+    egui::ComboBox::from_label(label)
+        .selected_text(name(value))
+        .show_ui(ui, |ui| {
+            for option in all {
+                changed |= ui.selectable_value(value, *option, name(option)).changed();
+            }
+        });
+    changed
+}
+
+/// Maps PDF user space of the previewed page to the screen and back, the way
+/// pdfium lays the page out: only its visible box, turned by `/Rotate`.
+#[derive(Debug, Clone, Copy)]
+struct PageTransform {
+    origin: Pos2,
+    scale: f32,
+    page_box: PageBox,
+    rotation: PageOrientation,
+}
+
+impl PageTransform {
+    /// `display_width` is the on-screen width of the rendered page.
+    fn new(origin: Pos2, display_width: f32, page_box: PageBox, rotation: PageOrientation) -> Self {
+        let page_width = match rotation {
+            PageOrientation::Normal | PageOrientation::Flip => page_box.width(),
+            PageOrientation::Clockwise | PageOrientation::CounterClockwise => page_box.height(),
+        };
+        PageTransform { origin, scale: display_width / page_width, page_box, rotation }
+    }
+
+    fn to_screen(self, x: f32, y: f32) -> Pos2 {
+        let (w, h) = (self.page_box.width(), self.page_box.height());
+        let (u, v) = (x - self.page_box.left, y - self.page_box.bottom);
+        // Screen y grows downwards, PDF y upwards
+        let (px, py) = match self.rotation {
+            PageOrientation::Normal => (u, h - v),
+            PageOrientation::Clockwise => (v, u),
+            PageOrientation::Flip => (w - u, v),
+            PageOrientation::CounterClockwise => (h - v, w - u),
+        };
+        pos2(self.origin.x + px * self.scale, self.origin.y + py * self.scale)
+    }
+
+    fn to_page(self, pos: Pos2) -> (f32, f32) {
+        let (w, h) = (self.page_box.width(), self.page_box.height());
+        let (px, py) = ((pos.x - self.origin.x) / self.scale, (pos.y - self.origin.y) / self.scale);
+        let (u, v) = match self.rotation {
+            PageOrientation::Normal => (px, h - py),
+            PageOrientation::Clockwise => (py, px),
+            PageOrientation::Flip => (w - px, py),
+            PageOrientation::CounterClockwise => (w - py, h - px),
+        };
+        (u + self.page_box.left, v + self.page_box.bottom)
+    }
+
+    fn slot_rect(&self, slot: &Slot) -> Rect {
+        Rect::from_two_pos(self.to_screen(slot.x, slot.y), self.to_screen(slot.x + slot.width, slot.y + slot.height))
+    }
+
+    /// Takes the slot's box from the screen, kept at `MIN_SLOT_SIZE` or more
+    /// by holding on to the edges that were dragged least.
+    fn set_slot_rect(&self, slot: &mut Slot, rect: Rect) {
+        let (x0, y0) = self.to_page(rect.min);
+        let (x1, y1) = self.to_page(rect.max);
+        (slot.x, slot.width) = at_least_min_size(x0.min(x1), x0.max(x1), slot.x, slot.x + slot.width);
+        (slot.y, slot.height) = at_least_min_size(y0.min(y1), y0.max(y1), slot.y, slot.y + slot.height);
+    }
+}
+
+/// Start and length of the span from `start` to `end`, widened to `MIN_SLOT_SIZE`
+/// away from whichever edge stayed closer to where it was.
+fn at_least_min_size(start: f32, end: f32, old_start: f32, old_end: f32) -> (f32, f32) {
+    if end - start >= MIN_SLOT_SIZE {
+        (start, end - start)
+    } else if (start - old_start).abs() <= (end - old_end).abs() {
+        (start, MIN_SLOT_SIZE)
+    } else {
+        (end - MIN_SLOT_SIZE, MIN_SLOT_SIZE)
+    }
+}
+
+/// Draws a texture through a PDF `cm` matrix, which maps the unit square onto the page.
+fn paint_transformed(painter: &egui::Painter, texture: egui::TextureId, m: [f32; 6], to_screen: impl Fn(f32, f32) -> egui::Pos2) {
+    let mut mesh = egui::Mesh::with_texture(texture);
+    for (u, v) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+        let pos = to_screen(m[0] * u + m[2] * v + m[4], m[1] * u + m[3] * v + m[5]);
+        // Image space starts at the bottom row, textures at the top one
+        mesh.vertices.push(egui::epaint::Vertex { pos, uv: pos2(u, 1.0 - v), color: Color32::WHITE });
+    }
+    mesh.add_triangle(0, 1, 2);
+    mesh.add_triangle(0, 2, 3);
+    painter.add(mesh);
 }
 
 fn draw_full_image(ui: &mut egui::Ui, image: &egui::TextureHandle) {
@@ -0,0 +1,616 @@
+use image::ImageFormat;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    net::{IpAddr, Ipv4Addr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::printer::{PrintStatus, PrintUpdate};
+use crate::printer_wrapper::JobState;
+use crate::watcher::{self, ClaimedPaths};
+
+pub const DEFAULT_PORT: u16 = 8080;
+const DEFAULT_MAX_SIZE_MB: u64 = 25;
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+/// How many uploads are remembered for status queries.
+const MAX_TRACKED_JOBS: usize = 1000;
+/// Besides the image types themselves, what camera apps tend to send raw bytes as.
+const ACCEPTED_TYPES: [&str; 3] = ["image/jpeg", "image/png", "application/octet-stream"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadSettings {
+    pub port: u16,
+    /// Larger uploads are turned away before they are read.
+    pub max_size_mb: u64,
+}
+
+impl Default for UploadSettings {
+    fn default() -> Self {
+        UploadSettings { port: DEFAULT_PORT, max_size_mb: DEFAULT_MAX_SIZE_MB }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct UploadJob {
+    id: u64,
+    image_path: String,
+    /// `None` until the inserter reports on the photo.
+    status: Option<PrintStatus>,
+}
+
+#[derive(Default)]
+struct JobList {
+    next_id: u64,
+    jobs: VecDeque<UploadJob>,
+}
+
+/// Uploads received so far and how printing them went, shared between the
+/// server and whoever receives the inserter's status updates.
+#[derive(Clone, Default)]
+pub struct UploadJobs(Arc<Mutex<JobList>>);
+
+impl UploadJobs {
+    fn add(&self, image_path: String) -> u64 {
+        let mut list = self.0.lock();
+        list.next_id += 1;
+        let id = list.next_id;
+        list.jobs.push_back(UploadJob { id, image_path, status: None });
+        if list.jobs.len() > MAX_TRACKED_JOBS {
+            list.jobs.pop_front();
+        }
+        id
+    }
+
+    fn get(&self, id: u64) -> Option<UploadJob> {
+        self.0.lock().jobs.iter().find(|j| j.id == id).cloned()
+    }
+
+    /// Records a status update from the inserter if it's about an upload.
+    pub fn update(&self, update: &PrintUpdate) {
+        let mut list = self.0.lock();
+        if let Some(job) = list.jobs.iter_mut().rev().find(|j| j.image_path == update.image_path) {
+            job.status = Some(update.status.clone());
+        }
+    }
+}
+
+/// Takes photo uploads over HTTP from phones or a booth camera app on the
+/// local network, stores them in the watched folder and hands them on like
+/// the folder watcher does.
+///
+/// `POST /upload` takes the image as the raw body or as the file of a form,
+/// and answers with a job id. `GET /jobs/<id>` tells how printing it went.
+pub struct UploadServer {
+    port: u16,
+    upload_dir: Arc<Mutex<PathBuf>>,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl UploadServer {
+    /// Listens on all interfaces, but only answers clients on the local network.
+    pub fn spawn(
+        settings: &UploadSettings,
+        upload_dir: PathBuf,
+        images_tx: Sender<String>,
+        claimed: ClaimedPaths,
+        jobs: UploadJobs,
+    ) -> io::Result<UploadServer> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, settings.port))?;
+        let port = listener.local_addr()?.port();
+        println!("Accepting uploads on port {} into {:?}", port, upload_dir);
+
+        // Like the paths the folder watcher reports, so claimed uploads match them
+        let upload_dir = Arc::new(Mutex::new(watcher::absolute(&upload_dir)));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let handler = Handler {
+            upload_dir: upload_dir.clone(),
+            images_tx,
+            claimed,
+            jobs,
+            max_size: settings.max_size_mb.saturating_mul(1024 * 1024) as usize,
+        };
+        let stopped_clone = stopped.clone();
+        let thread = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped_clone.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("Failed to accept an upload connection: {}", e);
+                        continue;
+                    }
+                };
+                // An upload over a slow phone connection shouldn't hold up the others
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handler.handle(stream) {
+                        eprintln!("Failed to answer an upload request: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(UploadServer { port, upload_dir, stopped, thread: Some(thread) })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Where the next uploads are stored, e.g. after the watched folder changed.
+    pub fn set_upload_dir(&self, dir: PathBuf) {
+        *self.upload_dir.lock() = watcher::absolute(&dir);
+    }
+
+    pub fn stop(&mut self) {
+        let Some(thread) = self.thread.take() else { return };
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake up the accept loop so it sees the flag
+        let _ = TcpStream::connect((Ipv4Addr::LOCALHOST, self.port));
+        if thread.join().is_err() {
+            eprintln!("The upload server thread panicked");
+        }
+    }
+}
+
+impl Drop for UploadServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Why a request was turned down, as the HTTP status to answer with.
+struct Rejection {
+    status: u16,
+    message: String,
+}
+
+fn reject(status: u16, message: impl Into<String>) -> Rejection {
+    Rejection { status, message: message.into() }
+}
+
+struct Request {
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Clone)]
+struct Handler {
+    upload_dir: Arc<Mutex<PathBuf>>,
+    images_tx: Sender<String>,
+    claimed: ClaimedPaths,
+    jobs: UploadJobs,
+    max_size: usize,
+}
+
+impl Handler {
+    fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let peer = stream.peer_addr()?;
+
+        let result = if is_local(peer.ip()) {
+            read_request(&mut stream, self.max_size).and_then(|request| self.route(&request))
+        } else {
+            Err(reject(403, "Uploads are only accepted from the local network"))
+        };
+        match result {
+            Ok((status, body)) => respond(&mut stream, status, &body),
+            Err(rejection) => {
+                eprintln!("Rejected a request from {}: {}", peer, rejection.message);
+                respond(&mut stream, rejection.status, &json!({ "error": rejection.message }))
+            }
+        }
+    }
+
+    fn route(&self, request: &Request) -> Result<(u16, Value), Rejection> {
+        let path = request.target.split('?').next().unwrap_or_default();
+        match (request.method.as_str(), path) {
+            ("POST", "/upload") => self.upload(request),
+            ("GET", _) if path.starts_with("/jobs/") => self.job_status(&path["/jobs/".len()..]),
+            (_, "/upload") => Err(reject(405, "Use POST to upload a photo")),
+            _ => Err(reject(404, "Not found")),
+        }
+    }
+
+    fn upload(&self, request: &Request) -> Result<(u16, Value), Rejection> {
+        let content_type = request.header("content-type").unwrap_or("application/octet-stream");
+        let (declared_type, data) = if content_type.to_ascii_lowercase().starts_with("multipart/form-data") {
+            multipart_file(&request.body, content_type).ok_or_else(|| reject(400, "The form holds no file"))?
+        } else {
+            (content_type.to_string(), &request.body[..])
+        };
+        let declared_type = declared_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        if !ACCEPTED_TYPES.contains(&declared_type.as_str()) {
+            return Err(reject(415, format!("Only JPEG and PNG photos are accepted, not {}", declared_type)));
+        }
+
+        // The declared type is only a hint, the contents decide
+        let extension = match image::guess_format(data) {
+            Ok(ImageFormat::Jpeg) => "jpg",
+            Ok(ImageFormat::Png) => "png",
+            _ => return Err(reject(415, "Only JPEG and PNG photos are accepted")),
+        };
+        image::load_from_memory(data).map_err(|e| reject(422, format!("The photo can't be decoded: {}", e)))?;
+
+        let path = self.save(data, extension)?;
+        let image_path = path.to_string_lossy().into_owned();
+        println!("Received upload {}", image_path);
+        let id = self.jobs.add(image_path.clone());
+        self.images_tx
+            .send(image_path)
+            .map_err(|_| reject(503, "Not taking photos right now"))?;
+        Ok((201, json!({ "job": id, "file": file_name(&path), "status": "received" })))
+    }
+
+    fn save(&self, data: &[u8], extension: &str) -> Result<PathBuf, Rejection> {
+        let dir = self.upload_dir.lock().clone();
+        let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let mut attempt = 0;
+        loop {
+            let name = match attempt {
+                0 => format!("upload-{}.{}", stamp, extension),
+                n => format!("upload-{}-{}.{}", stamp, n, extension),
+            };
+            let path = dir.join(name);
+            // Claimed before it exists, so the folder watcher never reports it as well
+            self.claimed.lock().insert(path.clone());
+            let written = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(data));
+            match written {
+                Ok(()) => return Ok(path),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    self.claimed.lock().remove(&path);
+                    attempt += 1;
+                }
+                Err(e) => {
+                    self.claimed.lock().remove(&path);
+                    let _ = fs::remove_file(&path);
+                    return Err(reject(500, format!("Failed to store the photo: {}", e)));
+                }
+            }
+        }
+    }
+
+    fn job_status(&self, id: &str) -> Result<(u16, Value), Rejection> {
+        let job = id
+            .parse()
+            .ok()
+            .and_then(|id| self.jobs.get(id))
+            .ok_or_else(|| reject(404, format!("No upload with job id {}", id)))?;
+        let (status, printed, failed) = match &job.status {
+            Some(status) => (
+                status.label(),
+                matches!(status, PrintStatus::Job(_, JobState::Completed) | PrintStatus::AlreadyPrinted),
+                status.is_failed(),
+            ),
+            None => ("received".to_string(), false, false),
+        };
+        Ok((
+            200,
+            json!({
+                "job": job.id,
+                "file": file_name(&PathBuf::from(&job.image_path)),
+                "status": status,
+                "printed": printed,
+                "failed": failed,
+            }),
+        ))
+    }
+}
+
+fn file_name(path: &std::path::Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Loopback, private and link-local addresses, which is what a booth's own
+/// network hands out.
+fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_local(IpAddr::V4(ip)),
+            // fc00::/7 unique local and fe80::/10 link-local
+            None => ip.is_loopback() || (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80,
+        },
+    }
+}
+
+fn read_request(stream: &mut TcpStream, max_size: usize) -> Result<Request, Rejection> {
+    let mut raw = Vec::new();
+    let mut buffer = [0u8; 4096];
+    let header_end = loop {
+        if let Some(pos) = find(&raw, b"\r\n\r\n") {
+            break pos;
+        }
+        if raw.len() > MAX_HEADER_SIZE {
+            return Err(reject(431, "The request headers are too large"));
+        }
+        let read = stream.read(&mut buffer).map_err(|e| reject(400, format!("Failed to read the request: {}", e)))?;
+        if read == 0 {
+            return Err(reject(400, "The connection closed before the request was complete"));
+        }
+        raw.extend_from_slice(&buffer[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&raw[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(reject(400, format!("Malformed request line: {}", request_line)));
+    };
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let mut request = Request { method: method.to_string(), target: target.to_string(), headers, body: Vec::new() };
+    if request.method != "POST" {
+        return Ok(request);
+    }
+
+    let length: usize = request
+        .header("content-length")
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| reject(411, "Uploads need a Content-Length"))?;
+    if length > max_size {
+        return Err(reject(413, format!("Uploads are limited to {} MB", max_size / (1024 * 1024))));
+    }
+    if request.header("expect").is_some_and(|v| v.eq_ignore_ascii_case("100-continue")) {
+        stream
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .map_err(|e| reject(400, format!("Failed to answer the client: {}", e)))?;
+    }
+
+    let mut body = raw.split_off(header_end + 4);
+    if body.len() < length {
+        let start = body.len();
+        body.resize(length, 0);
+        stream
+            .read_exact(&mut body[start..])
+            .map_err(|e| reject(400, format!("Failed to read the upload: {}", e)))?;
+    }
+    body.truncate(length);
+    request.body = body;
+    Ok(request)
+}
+
+/// The first file of a multipart/form-data body, with its declared content type.
+fn multipart_file<'a>(body: &'a [u8], content_type: &str) -> Option<(String, &'a [u8])> {
+    let boundary = content_type
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("boundary="))?
+        .trim_matches('"');
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let part_end = [b"\r\n".as_slice(), &delimiter].concat();
+
+    let mut rest = body;
+    loop {
+        rest = &rest[find(rest, &delimiter)? + delimiter.len()..];
+        // The closing delimiter ends in "--"
+        if rest.starts_with(b"--") {
+            return None;
+        }
+        let headers_end = find(rest, b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&rest[..headers_end]);
+        let content = &rest[headers_end + 4..];
+        let end = find(content, &part_end)?;
+
+        let mut is_file = false;
+        let mut part_type = "application/octet-stream".to_string();
+        for (name, value) in headers.lines().filter_map(|line| line.split_once(':')) {
+            if name.trim().eq_ignore_ascii_case("content-disposition") && value.contains("filename=") {
+                is_file = true;
+            } else if name.trim().eq_ignore_ascii_case("content-type") {
+                part_type = value.trim().to_string();
+            }
+        }
+        if is_file {
+            return Some((part_type, &content[..end]));
+        }
+        rest = &content[end..];
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn respond(stream: &mut TcpStream, status: u16, body: &Value) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        422 => "Unprocessable Entity",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Shutdown;
+
+    /// Runs `client` against a fresh connection and hands back the server's end of it.
+    fn connect<T: Send + 'static>(client: impl FnOnce(TcpStream) -> T + Send + 'static) -> (TcpStream, JoinHandle<T>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || client(TcpStream::connect(address).unwrap()));
+        let (stream, _) = listener.accept().unwrap();
+        (stream, client)
+    }
+
+    /// The request as the server reads it when the client sends `raw` in one go.
+    fn read(raw: &'static [u8], max_size: usize) -> Result<Request, Rejection> {
+        let (mut stream, client) = connect(move |mut stream| {
+            stream.write_all(raw).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+        });
+        let request = read_request(&mut stream, max_size);
+        client.join().unwrap();
+        request
+    }
+
+    fn multipart(parts: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (headers, content) in parts {
+            body.extend_from_slice(format!("--xYz\r\n{}\r\n\r\n", headers).as_bytes());
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--xYz--\r\n");
+        body
+    }
+
+    #[test]
+    fn body_is_read_up_to_the_content_length() {
+        let request = read(b"POST /upload HTTP/1.1\r\nHost: booth\r\ncontent-LENGTH: 5\r\nContent-Type: image/jpeg\r\n\r\nhello, and more", 1024)
+            .unwrap_or_else(|r| panic!("{}", r.message));
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.target, "/upload");
+        assert_eq!(request.header("Content-Type"), Some("image/jpeg"));
+        assert_eq!(request.body, b"hello");
+    }
+
+    #[test]
+    fn body_arriving_after_the_headers_is_waited_for() {
+        let (mut stream, client) = connect(|mut stream| {
+            stream.write_all(b"POST /upload HTTP/1.1\r\nContent-Length: 10\r\n\r\n0123").unwrap();
+            thread::sleep(Duration::from_millis(50));
+            stream.write_all(b"456789").unwrap();
+        });
+        let request = read_request(&mut stream, 1024).unwrap_or_else(|r| panic!("{}", r.message));
+        client.join().unwrap();
+        assert_eq!(request.body, b"0123456789");
+    }
+
+    #[test]
+    fn requests_without_a_body() {
+        let request = read(b"GET /jobs/3 HTTP/1.1\r\nHost: booth\r\n\r\n", 1024).unwrap_or_else(|r| panic!("{}", r.message));
+        assert_eq!((request.method.as_str(), request.target.as_str()), ("GET", "/jobs/3"));
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn bad_uploads_are_rejected() {
+        let status = |raw| read(raw, 1024).err().map(|r| r.status);
+        assert_eq!(status(b"POST /upload HTTP/1.1\r\n\r\n"), Some(411));
+        assert_eq!(status(b"POST /upload HTTP/1.1\r\nContent-Length: 2048\r\n\r\n"), Some(413));
+        assert_eq!(status(b"POST /upload HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"), Some(400));
+        assert_eq!(status(b"garbage\r\n\r\n"), Some(400));
+    }
+
+    #[test]
+    fn expect_100_continue_is_answered_before_the_body() {
+        let (mut stream, client) = connect(|mut stream| {
+            stream.write_all(b"POST /upload HTTP/1.1\r\nContent-Length: 4\r\nExpect: 100-continue\r\n\r\n").unwrap();
+            // Like curl, the body only follows once the server agreed
+            let mut interim = [0u8; 25];
+            stream.read_exact(&mut interim).unwrap();
+            stream.write_all(b"data").unwrap();
+            interim
+        });
+        let request = read_request(&mut stream, 1024).unwrap_or_else(|r| panic!("{}", r.message));
+        assert_eq!(&client.join().unwrap(), b"HTTP/1.1 100 Continue\r\n\r\n");
+        assert_eq!(request.body, b"data");
+    }
+
+    #[test]
+    fn multipart_file_skips_plain_fields() {
+        let body = multipart(&[
+            ("Content-Disposition: form-data; name=\"caption\"", b"hello"),
+            ("Content-Disposition: form-data; name=\"photo\"; filename=\"a.jpg\"\r\nContent-Type: image/jpeg", b"\xFF\xD8\r\n--xY\xFF\xD9"),
+        ]);
+        let (part_type, data) = multipart_file(&body, "multipart/form-data; boundary=\"xYz\"").unwrap();
+        assert_eq!(part_type, "image/jpeg");
+        assert_eq!(data, b"\xFF\xD8\r\n--xY\xFF\xD9");
+    }
+
+    #[test]
+    fn multipart_file_defaults_to_octet_stream() {
+        let body = multipart(&[("Content-Disposition: form-data; name=\"photo\"; filename=\"a.jpg\"", b"data")]);
+        let (part_type, data) = multipart_file(&body, "multipart/form-data; boundary=xYz").unwrap();
+        assert_eq!(part_type, "application/octet-stream");
+        assert_eq!(data, b"data");
+    }
+
+    #[test]
+    fn multipart_without_a_file() {
+        let body = multipart(&[("Content-Disposition: form-data; name=\"caption\"", b"hello")]);
+        assert!(multipart_file(&body, "multipart/form-data; boundary=xYz").is_none());
+        // Without a boundary the parts can't be told apart
+        assert!(multipart_file(&body, "multipart/form-data").is_none());
+        assert!(multipart_file(b"--xYz\r\nno end", "multipart/form-data; boundary=xYz").is_none());
+    }
+
+    #[test]
+    fn only_local_peers_are_local() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.9", "192.168.178.20", "169.254.1.1", "::1", "fd12::1", "fe80::1", "::ffff:192.168.1.2"] {
+            assert!(is_local(ip.parse().unwrap()), "{} is local", ip);
+        }
+        for ip in ["8.8.8.8", "172.32.0.1", "100.64.0.1", "2001:db8::1", "2a00:1450::1", "::ffff:8.8.8.8"] {
+            assert!(!is_local(ip.parse().unwrap()), "{} isn't local", ip);
+        }
+    }
+
+    #[test]
+    fn responses_carry_status_and_json() {
+        let (mut stream, client) = connect(|mut stream| {
+            let mut answer = String::new();
+            stream.read_to_string(&mut answer).unwrap();
+            answer
+        });
+        respond(&mut stream, 403, &json!({ "error": "no" })).unwrap();
+        drop(stream);
+        let answer = client.join().unwrap();
+        assert!(answer.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(answer.contains("Content-Length: 14\r\n"));
+        assert!(answer.ends_with("\r\n\r\n{\"error\":\"no\"}"));
+    }
+}
@@ -1,58 +1,797 @@
-use notify::{RecommendedWatcher, RecursiveMode, Watcher, Config, EventKind};
-use std::sync::mpsc::{self, Sender};
-use std::thread;
-use std::path::PathBuf;
+use glob::{MatchOptions, Pattern};
+use image::ImageReader;
+use notify::{RecommendedWatcher, PollWatcher, RecursiveMode, Watcher, Config, Event, EventKind};
+use notify::event::{AccessKind, AccessMode, CreateKind, MetadataKind, ModifyKind, RenameMode};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc::{self, Receiver, Sender, RecvTimeoutError}, Arc, Weak};
+use std::thread::{self, JoinHandle};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 //TODO: Can potentialy remove this thread that runs in between, and get recv messages directly from notify internal thread
 
+const IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+const SETTLE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How long a file has to stay unchanged before it counts as completely written,
+/// for platforms and network shares that don't report close-write.
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+/// A file that still can't be decoded this long after its last change is given up on.
+const GIVE_UP_AFTER: Duration = Duration::from_secs(60);
+/// How often the watched folders are checked for having gone away or come back.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 2;
+
+/// How new files in the watched folders are noticed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatchBackend {
+    /// Polling for network shares, file system events for everything else.
+    #[default]
+    Auto,
+    Events,
+    /// Lists the folders every `poll_interval_secs`, for shares that don't
+    /// report writes from other machines.
+    Polling,
+}
+
+impl WatchBackend {
+    pub const ALL: [WatchBackend; 3] = [WatchBackend::Auto, WatchBackend::Events, WatchBackend::Polling];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WatchBackend::Auto => "Automatic",
+            WatchBackend::Events => "File events",
+            WatchBackend::Polling => "Polling",
+        }
+    }
+}
+
+/// Which files in the watched folders count as new photos, and how they are noticed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchSettings {
+    /// Also pick up photos written into subfolders, e.g. dated camera folders.
+    pub recursive: bool,
+    /// Glob patterns a photo has to match, all photos if empty. Patterns
+    /// without a `/` are matched against the file name, others against the
+    /// path relative to the watched folder.
+    pub include: Vec<String>,
+    /// Glob patterns of photos to skip, matched like `include`.
+    pub exclude: Vec<String>,
+    pub backend: WatchBackend,
+    pub poll_interval_secs: u64,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        WatchSettings {
+            recursive: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            backend: WatchBackend::default(),
+            poll_interval_secs: DEFAULT_POLL_INTERVAL_SECS,
+        }
+    }
+}
+
+struct PathFilter {
+    roots: Vec<PathBuf>,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl PathFilter {
+    fn new(roots: Vec<PathBuf>, settings: &WatchSettings) -> Result<Self, glob::PatternError> {
+        let compile = |patterns: &[String]| patterns.iter().map(|p| Pattern::new(p)).collect::<Result<Vec<_>, _>>();
+        Ok(PathFilter {
+            roots,
+            include: compile(&settings.include)?,
+            exclude: compile(&settings.exclude)?,
+        })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let is_image = path
+            .extension()
+            .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_string_lossy().to_ascii_lowercase().as_str()))
+            .unwrap_or(false);
+        if !is_image {
+            return false;
+        }
+
+        let relative = self
+            .roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let options = MatchOptions { case_sensitive: false, ..MatchOptions::new() };
+        let matches = |pattern: &Pattern| {
+            let target = if pattern.as_str().contains('/') { &relative } else { &file_name };
+            pattern.matches_with(target, options)
+        };
+
+        (self.include.is_empty() || self.include.iter().any(matches)) && !self.exclude.iter().any(matches)
+    }
+}
+
+/// Size and modification time, to notice a file is still being written.
+type FileVersion = (u64, Option<SystemTime>);
+
+/// A file that showed up in a watched folder but may still be being written.
+struct PendingFile {
+    version: Option<FileVersion>,
+    changed_at: Instant,
+    /// The writer closed the file, no need to wait for it to settle if it looks complete.
+    closed: bool,
+    /// The version that last failed to decode, so it isn't decoded again until it changes.
+    failed: Option<FileVersion>,
+}
+
+impl PendingFile {
+    fn new() -> Self {
+        PendingFile { version: None, changed_at: Instant::now(), closed: false, failed: None }
+    }
+}
+
+/// Files an event says were written to, and whether the writer is done with them.
+fn written_paths(event: &Event) -> Option<(&[PathBuf], bool)> {
+    match event.kind {
+        EventKind::Create(CreateKind::File | CreateKind::Any)
+        | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any)
+        // What polling reports for a file written to since the last look
+        | EventKind::Modify(ModifyKind::Metadata(MetadataKind::WriteTime)) => Some((&event.paths, false)),
+        // Moved into the folder, e.g. renamed from a temporary name once the upload finished
+        EventKind::Modify(ModifyKind::Name(RenameMode::To | RenameMode::Any)) => Some((&event.paths, false)),
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => Some((&event.paths[event.paths.len().saturating_sub(1)..], false)),
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => Some((&event.paths, true)),
+        _ => None,
+    }
+}
+
+/// Whether the file ends the way a finished JPEG or PNG does. Some writers
+/// close and reopen a file while writing it, so close-write alone isn't enough.
+fn has_end_marker(path: &Path) -> bool {
+    let mut tail = [0u8; 12];
+    let read_tail = |tail: &mut [u8]| -> std::io::Result<()> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::End(-(tail.len() as i64)))?;
+        file.read_exact(tail)
+    };
+    if read_tail(&mut tail).is_err() {
+        return false;
+    }
+    tail.ends_with(&[0xFF, 0xD9]) || &tail[4..8] == b"IEND"
+}
+
+fn is_decodable(path: &Path) -> bool {
+    ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map(|reader| reader.decode().is_ok())
+        .unwrap_or(false)
+}
+
+/// Returns the pending files that are complete and decodable, and forgets
+/// those that vanished or never became a valid image.
+fn settle(pending: &mut HashMap<PathBuf, PendingFile>) -> Vec<PathBuf> {
+    let now = Instant::now();
+    let mut ready = Vec::new();
+    pending.retain(|path, file| {
+        let Ok(meta) = fs::metadata(path) else {
+            // Moved away or deleted before it was finished
+            return false;
+        };
+        let version = Some((meta.len(), meta.modified().ok()));
+        if version != file.version {
+            file.version = version;
+            file.changed_at = now;
+        }
+        let finished = file.closed && has_end_marker(path);
+        if !finished && now.duration_since(file.changed_at) < SETTLE_TIME {
+            return true;
+        }
+        if file.failed != file.version {
+            if is_decodable(path) {
+                ready.push(path.clone());
+                return false;
+            }
+            file.failed = file.version;
+            file.closed = false;
+        }
+        if now.duration_since(file.changed_at) > GIVE_UP_AFTER {
+            eprintln!("Skipping {:?}, it is not a readable image", path);
+            return false;
+        }
+        true
+    });
+    ready
+}
+
+/// The folder as it's watched and reported in `FolderStatus`.
+pub fn absolute(folder: &Path) -> PathBuf {
+    std::path::absolute(folder).unwrap_or_else(|_| folder.to_path_buf())
+}
+
+/// Photos already in `folders` that the filter lets through, oldest capture first.
+fn existing_images(folders: &[PathBuf], filter: &PathFilter, recursive: bool) -> Vec<PathBuf> {
+    let mut images = Vec::new();
+    let mut dirs = folders.to_vec();
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Failed to list {:?}: {}", dir, e);
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() && recursive => dirs.push(path),
+                Ok(kind) if kind.is_file() && filter.matches(&path) => images.push(path),
+                _ => {}
+            }
+        }
+    }
+
+    let mut dated: Vec<(SystemTime, PathBuf)> = images.into_iter().map(|path| (capture_time(&path), path)).collect();
+    dated.sort();
+    dated.into_iter().map(|(_, path)| path).collect()
+}
+
+/// When the photo was taken according to its EXIF data, or else when the file was last written.
+fn capture_time(path: &Path) -> SystemTime {
+    exif_capture_time(path)
+        .or_else(|| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .unwrap_or(UNIX_EPOCH)
+}
+
+/// DateTimeOriginal of a JPEG, falling back to its DateTime. EXIF doesn't say
+/// which time zone the camera was set to, so this is read as UTC.
+fn exif_capture_time(path: &Path) -> Option<SystemTime> {
+    // The EXIF segment sits at the start of the file and is at most 64 KiB
+    let mut head = Vec::new();
+    File::open(path).ok()?.take(128 * 1024).read_to_end(&mut head).ok()?;
+    let tiff = Tiff::from_jpeg(&head)?;
+    let entry = tiff
+        .find_tag(tiff.ifd0()?, 0x8769)
+        .and_then(|exif_ifd| tiff.find_tag(tiff.u32_at(exif_ifd + 8)? as usize, 0x9003))
+        .or_else(|| tiff.find_tag(tiff.ifd0()?, 0x0132))?;
+    parse_exif_time(tiff.ascii(entry)?)
+}
+
+/// The TIFF structure inside a JPEG's EXIF segment.
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn from_jpeg(jpeg: &'a [u8]) -> Option<Tiff<'a>> {
+        if !jpeg.starts_with(&[0xFF, 0xD8]) {
+            return None;
+        }
+        let mut pos = 2;
+        while pos + 4 <= jpeg.len() {
+            if jpeg[pos] != 0xFF {
+                return None;
+            }
+            let marker = jpeg[pos + 1];
+            // Start of scan, there is no EXIF segment after the image data starts
+            if marker == 0xDA {
+                return None;
+            }
+            let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+            let segment = jpeg.get(pos + 4..pos + 2 + len)?;
+            if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+                let data = &segment[6..];
+                let big_endian = match data.get(..2)? {
+                    b"MM" => true,
+                    b"II" => false,
+                    _ => return None,
+                };
+                return Some(Tiff { data, big_endian });
+            }
+            pos += 2 + len;
+        }
+        None
+    }
+
+    fn u16_at(&self, pos: usize) -> Option<u16> {
+        let bytes = self.data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32_at(&self, pos: usize) -> Option<u32> {
+        let bytes = self.data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn ifd0(&self) -> Option<usize> {
+        self.u32_at(4).map(|offset| offset as usize)
+    }
+
+    /// Position of the entry for `tag` in the directory at `ifd`.
+    fn find_tag(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16_at(ifd)? as usize;
+        (0..count).map(|i| ifd + 2 + i * 12).find(|&entry| self.u16_at(entry) == Some(tag))
+    }
+
+    fn ascii(&self, entry: usize) -> Option<&'a str> {
+        let len = self.u32_at(entry + 4)? as usize;
+        let offset = if len <= 4 { entry + 8 } else { self.u32_at(entry + 8)? as usize };
+        std::str::from_utf8(self.data.get(offset..offset + len)?).ok()
+    }
+}
+
+/// Parses an EXIF timestamp, "YYYY:MM:DD HH:MM:SS".
+fn parse_exif_time(text: &str) -> Option<SystemTime> {
+    let number = |range: Range<usize>| text.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let seconds = number(11..13)? * 3600 + number(14..16)? * 60 + number(17..19)?;
+    // Cameras without a set clock write zeros
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since 1970-01-01 in the Gregorian calendar, counting years from March
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    let secs = u64::try_from(days * 86400 + seconds).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Where a watched folder stands, for the UI and the logs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderStatus {
+    pub folder: PathBuf,
+    /// Watched by polling instead of file system events.
+    pub polling: bool,
+    /// Why the folder can't be watched, `None` while everything is fine.
+    pub error: Option<String>,
+}
+
+/// The notify watchers, created as folders need them.
+struct Watchers {
+    tx: Sender<WatchMessage>,
+    poll_interval: Duration,
+    events: Option<RecommendedWatcher>,
+    polling: Option<PollWatcher>,
+}
+
+impl Watchers {
+    fn handler(&self) -> impl FnMut(notify::Result<Event>) + Send + 'static {
+        let tx = self.tx.clone();
+        // The forwarding thread may already be gone while notify shuts down
+        move |res| {
+            let _ = tx.send(WatchMessage::Event(res));
+        }
+    }
+
+    fn watch(&mut self, folder: &Path, polling: bool, mode: RecursiveMode) -> notify::Result<()> {
+        if polling {
+            if self.polling.is_none() {
+                let config = Config::default().with_poll_interval(self.poll_interval);
+                self.polling = Some(PollWatcher::new(self.handler(), config)?);
+            }
+            self.polling.as_mut().unwrap().watch(folder, mode)
+        } else {
+            if self.events.is_none() {
+                self.events = Some(RecommendedWatcher::new(self.handler(), Config::default())?);
+            }
+            self.events.as_mut().unwrap().watch(folder, mode)
+        }
+    }
+}
+
+struct WatchedFolder {
+    path: PathBuf,
+    polling: bool,
+    /// Fall back to polling if file system events can't be set up.
+    fallback: bool,
+    watched: bool,
+    /// Since when the folder couldn't be watched, to pick up what was written in the meantime.
+    lost_at: Option<SystemTime>,
+    error: Option<String>,
+}
+
+impl WatchedFolder {
+    fn new(path: PathBuf, backend: WatchBackend) -> Self {
+        let polling = match backend {
+            WatchBackend::Auto => is_network_share(&path),
+            WatchBackend::Events => false,
+            WatchBackend::Polling => true,
+        };
+        WatchedFolder { path, polling, fallback: backend == WatchBackend::Auto, watched: false, lost_at: None, error: None }
+    }
+
+    fn status(&self) -> FolderStatus {
+        FolderStatus { folder: self.path.clone(), polling: self.polling, error: self.error.clone() }
+    }
+
+    /// Checks the folder is still there and watches it again once it's back.
+    /// Returns since when it was gone if it just came back.
+    fn check(&mut self, watchers: &mut Watchers, mode: RecursiveMode) -> Option<SystemTime> {
+        let error = match fs::metadata(&self.path) {
+            Ok(meta) if meta.is_dir() => None,
+            Ok(_) => Some("not a folder".to_string()),
+            Err(e) => Some(e.to_string()),
+        };
+        if let Some(e) = error {
+            if self.error.is_none() {
+                eprintln!("Watched folder {:?} is unreachable: {}", self.path, e);
+            }
+            self.error = Some(e);
+            // An event based watch doesn't survive the folder going away, a
+            // polling one reports what's new on its own once it's back
+            if !self.polling {
+                self.watched = false;
+            }
+            self.lost_at.get_or_insert_with(SystemTime::now);
+            return None;
+        }
+        if self.watched {
+            if self.error.take().is_some() {
+                println!("Watched folder {:?} is reachable again", self.path);
+            }
+            self.lost_at = None;
+            return None;
+        }
+
+        let mut result = watchers.watch(&self.path, self.polling, mode);
+        if let (Err(e), true, false) = (&result, self.fallback, self.polling) {
+            eprintln!("File events don't work for {:?} ({}), polling it instead", self.path, e);
+            self.polling = true;
+            result = watchers.watch(&self.path, self.polling, mode);
+        }
+        match result {
+            Ok(()) => {
+                if self.error.take().is_some() {
+                    println!("Watched folder {:?} is reachable again", self.path);
+                }
+                self.watched = true;
+                self.lost_at.take()
+            }
+            Err(e) => {
+                let e = e.to_string();
+                if self.error.as_ref() != Some(&e) {
+                    eprintln!("Failed to watch {:?}: {}", self.path, e);
+                }
+                self.error = Some(e);
+                self.lost_at.get_or_insert_with(SystemTime::now);
+                None
+            }
+        }
+    }
+}
+
+/// Whether `folder` is on a network share, where writes from other machines
+/// don't cause file system events.
+#[cfg(target_os = "linux")]
+fn is_network_share(folder: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    // NFS, SMB, CIFS, SMB2 and 9P
+    const NETWORK_FILESYSTEMS: [u32; 5] = [0x6969, 0x517B, 0xFF534D42, 0xFE534D42, 0x01021997];
+
+    let Ok(path) = CString::new(folder.as_os_str().as_bytes()) else { return false };
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stats) } != 0 {
+        return false;
+    }
+    NETWORK_FILESYSTEMS.contains(&(stats.f_type as u32))
+}
+
+#[cfg(target_os = "macos")]
+fn is_network_share(folder: &Path) -> bool {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+    const NETWORK_FILESYSTEMS: [&str; 4] = ["nfs", "smbfs", "afpfs", "webdav"];
+
+    let Ok(path) = CString::new(folder.as_os_str().as_bytes()) else { return false };
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stats) } != 0 {
+        return false;
+    }
+    let name = unsafe { CStr::from_ptr(stats.f_fstypename.as_ptr()) };
+    NETWORK_FILESYSTEMS.contains(&name.to_string_lossy().as_ref())
+}
+
+/// Only UNC paths are recognized, a share mapped to a drive letter needs the polling backend picked by hand.
+#[cfg(target_os = "windows")]
+fn is_network_share(folder: &Path) -> bool {
+    use std::path::{Component, Prefix};
+    match folder.components().next() {
+        Some(Component::Prefix(prefix)) => matches!(prefix.kind(), Prefix::UNC(..) | Prefix::VerbatimUNC(..)),
+        _ => false,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn is_network_share(_folder: &Path) -> bool {
+    false
+}
+
+/// Files the app writes into the watched folders itself and hands on through
+/// the same channel, which the watcher must not report a second time.
+pub type ClaimedPaths = Arc<Mutex<HashSet<PathBuf>>>;
+
+/// What the watcher as a whole is doing, for `MyApp` to show.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatcherState {
+    /// Not watching anything, either no folders were picked or watching was stopped.
+    Paused,
+    Watching,
+    /// Still running, but some folders can't be watched or events were lost.
+    Error(String),
+}
+
+impl WatcherState {
+    pub fn label(&self) -> String {
+        match self {
+            WatcherState::Paused => "paused".to_string(),
+            WatcherState::Watching => "watching".to_string(),
+            WatcherState::Error(e) => format!("error: {}", e),
+        }
+    }
+}
+
+struct Report {
+    state: WatcherState,
+    statuses: Vec<FolderStatus>,
+}
+
+/// Sent to the forwarding thread, by notify and by `FolderWatcher::stop`.
+enum WatchMessage {
+    Event(notify::Result<Event>),
+    Stop,
+}
+
+/// The forwarding thread, as far as `FolderWatcher` needs to reach it.
+struct Worker {
+    control: Sender<WatchMessage>,
+    stopped: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// The background side of `FolderWatcher`: turns notify events into paths of
+/// completely written photos, and keeps an eye on the watched folders.
+struct Forwarder {
+    events: Receiver<WatchMessage>,
+    tx: Sender<String>,
+    filter: PathFilter,
+    folders: Vec<WatchedFolder>,
+    mode: RecursiveMode,
+    watchers: Weak<Mutex<Watchers>>,
+    report: Arc<Mutex<Report>>,
+    claimed: ClaimedPaths,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Forwarder {
+    fn run(mut self) {
+        // Files are only sent on once they are completely written
+        let mut pending: HashMap<PathBuf, PendingFile> = HashMap::new();
+        // Checking on the files opens them, which causes events of its own,
+        // so they are only checked at an interval rather than on every event
+        let mut next_settle = Instant::now();
+        let mut next_check = Instant::now() + HEALTH_CHECK_INTERVAL;
+        let mut watch_errors = Vec::new();
+        loop {
+            let wake_at = if pending.is_empty() { next_check } else { next_settle.min(next_check) };
+            match self.events.recv_timeout(wake_at.saturating_duration_since(Instant::now())) {
+                Ok(WatchMessage::Event(Ok(event))) => {
+                    if let Some((paths, closed)) = written_paths(&event) {
+                        for path in paths.iter().filter(|path| self.filter.matches(path)) {
+                            let file = pending.entry(path.clone()).or_insert_with(PendingFile::new);
+                            file.changed_at = Instant::now();
+                            file.closed |= closed;
+                        }
+                        if closed {
+                            next_settle = Instant::now();
+                        }
+                    }
+                }
+                Ok(WatchMessage::Event(Err(e))) => {
+                    // Likely the folder went away, check on it right away
+                    watch_errors.push(e);
+                    next_check = Instant::now();
+                }
+                Ok(WatchMessage::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            if Instant::now() >= next_check {
+                let Some(watchers) = self.watchers.upgrade() else { break };
+                for folder in &mut self.folders {
+                    let Some(lost_at) = folder.check(&mut watchers.lock(), self.mode) else { continue };
+                    // Pick up what was written while the folder was gone, with some slack
+                    // for the time between the last good check and noticing it was gone
+                    let since = lost_at.checked_sub(HEALTH_CHECK_INTERVAL).unwrap_or(lost_at);
+                    let recursive = self.mode == RecursiveMode::Recursive;
+                    for path in existing_images(std::slice::from_ref(&folder.path), &self.filter, recursive) {
+                        let modified = fs::metadata(&path).and_then(|meta| meta.modified());
+                        if modified.map(|time| time >= since).unwrap_or(false) {
+                            pending.entry(path).or_insert_with(PendingFile::new);
+                        }
+                    }
+                }
+
+                // Polling a missing folder fails every time, the check reports that once
+                let lost = |path: &PathBuf| self.folders.iter().any(|f| f.error.is_some() && path.starts_with(&f.path));
+                let errors: Vec<String> = watch_errors
+                    .drain(..)
+                    .filter(|e| !e.paths.iter().any(lost))
+                    .map(|e| {
+                        eprintln!("Watch error: {:?}", e);
+                        e.to_string()
+                    })
+                    .collect();
+                self.report_state(errors);
+                next_check = Instant::now() + HEALTH_CHECK_INTERVAL;
+            }
+
+            if !pending.is_empty() && Instant::now() >= next_settle {
+                for path in settle(&mut pending) {
+                    if self.claimed.lock().remove(&path) {
+                        continue;
+                    }
+                    // Nothing from the old folders once stopped, and nobody to send to once the receiver is gone
+                    if self.stopped.load(Ordering::SeqCst) || self.tx.send(path.to_string_lossy().into_owned()).is_err() {
+                        return;
+                    }
+                }
+                next_settle = Instant::now() + SETTLE_POLL_INTERVAL;
+            }
+        }
+    }
+
+    fn report_state(&self, mut errors: Vec<String>) {
+        errors.extend(
+            self.folders
+                .iter()
+                .filter_map(|f| f.error.as_ref().map(|e| format!("{}: {}", f.path.display(), e))),
+        );
+        let mut report = self.report.lock();
+        report.state = if errors.is_empty() { WatcherState::Watching } else { WatcherState::Error(errors.join("; ")) };
+        report.statuses = self.folders.iter().map(WatchedFolder::status).collect();
+    }
+}
+
 pub struct FolderWatcher {
-    watcher: Option<RecommendedWatcher>,
+    watchers: Option<Arc<Mutex<Watchers>>>,
+    worker: Option<Worker>,
+    report: Arc<Mutex<Report>>,
+    claimed: ClaimedPaths,
+    /// What the last `spawn_watcher` was given, for `restart`.
+    folders: Vec<PathBuf>,
+    settings: WatchSettings,
     tx: Sender<String>,
 }
 
 impl FolderWatcher {
     pub fn new(tx: Sender<String>) -> Self {
         Self {
-            watcher: None,
+            watchers: None,
+            worker: None,
+            report: Arc::new(Mutex::new(Report { state: WatcherState::Paused, statuses: Vec::new() })),
+            claimed: ClaimedPaths::default(),
+            folders: Vec::new(),
+            settings: WatchSettings::default(),
             tx,
         }
     }
-    
-    pub fn spawn_watcher(&mut self, path: PathBuf) -> notify::Result<()> {
-        println!("Spawning watcher at path: {:?}", path);
-        if let Some(mut w) = self.watcher.take() {
-            w.unwatch(&path)?;
+
+    pub fn state(&self) -> WatcherState {
+        self.report.lock().state.clone()
+    }
+
+    pub fn claimed(&self) -> ClaimedPaths {
+        self.claimed.clone()
+    }
+
+    /// How each watched folder is doing, updated in the background.
+    pub fn statuses(&self) -> Vec<FolderStatus> {
+        self.report.lock().statuses.clone()
+    }
+
+    /// Starts watching `folders`, replacing whatever was watched before.
+    /// Returns the photos that were already in them, oldest first, for the
+    /// caller to import or print as it sees fit. A folder that can't be
+    /// reached is reported in `statuses` and picked up once it's back.
+    pub fn spawn_watcher(&mut self, folders: &[PathBuf], settings: &WatchSettings) -> notify::Result<Vec<String>> {
+        println!("Spawning watcher at paths: {:?}", folders);
+        self.stop();
+        self.folders = folders.to_vec();
+        self.settings = settings.clone();
+        if folders.is_empty() {
+            return Ok(Vec::new());
         }
+        // Events report absolute paths, so the filter's roots must be absolute too
+        let folders: Vec<PathBuf> = folders.iter().map(|f| absolute(f)).collect();
 
-        let tx_clone = self.tx.clone();
+        let filter = match PathFilter::new(folders.clone(), settings) {
+            Ok(filter) => filter,
+            Err(e) => {
+                let message = format!("Invalid pattern: {}", e);
+                self.report.lock().state = WatcherState::Error(message.clone());
+                return Err(notify::Error::generic(&message));
+            }
+        };
         let (watcher_tx, watcher_rx) = mpsc::channel();
 
-        let mut watcher = RecommendedWatcher::new(watcher_tx, Config::default())?;
-        watcher.watch(&path, RecursiveMode::NonRecursive)?;
-
-        thread::spawn(move || {
-            for res in watcher_rx {
-                match res {
-                    Ok(event) => {
-                        if matches!(event.kind, EventKind::Create(_)) {
-
-                            for path in event.paths {
-                                if let Some(ext) = path.extension() {
-                                    if ext == "png" || ext == "jpg" || ext == "jpeg" {
-                                        std::thread::sleep(std::time::Duration::from_millis(100));
-                                        tx_clone.send(path.to_string_lossy().into_owned()).unwrap();
-                                    }
-                                
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => eprintln!("Watch error: {:?}", e),
-                }
-            }
-        });
+        let mut watchers = Watchers {
+            tx: watcher_tx.clone(),
+            poll_interval: Duration::from_secs(settings.poll_interval_secs.max(1)),
+            events: None,
+            polling: None,
+        };
+        let mode = if settings.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        let mut watched: Vec<WatchedFolder> = folders.iter().map(|f| WatchedFolder::new(f.clone(), settings.backend)).collect();
+        for folder in &mut watched {
+            folder.check(&mut watchers, mode);
+        }
+        let watchers = Arc::new(Mutex::new(watchers));
+
+        // Listed after watching started, so nothing written in between is missed
+        let existing = existing_images(&folders, &filter, settings.recursive);
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let forwarder = Forwarder {
+            events: watcher_rx,
+            tx: self.tx.clone(),
+            filter,
+            folders: watched,
+            mode,
+            watchers: Arc::downgrade(&watchers),
+            report: self.report.clone(),
+            claimed: self.claimed.clone(),
+            stopped: stopped.clone(),
+        };
+        forwarder.report_state(Vec::new());
+        let thread = thread::spawn(move || forwarder.run());
 
-        self.watcher = Some(watcher);
-        Ok(())
+        self.watchers = Some(watchers);
+        self.worker = Some(Worker { control: watcher_tx, stopped, thread });
+        Ok(existing.into_iter().map(|path| path.to_string_lossy().into_owned()).collect())
+    }
+
+    /// Watches the folders of the last `spawn_watcher` again after `stop`.
+    pub fn restart(&mut self) -> notify::Result<Vec<String>> {
+        let folders = self.folders.clone();
+        let settings = self.settings.clone();
+        self.spawn_watcher(&folders, &settings)
+    }
+
+    /// Stops watching and waits for the forwarding thread to finish, so
+    /// nothing more arrives from the folders watched so far.
+    pub fn stop(&mut self) {
+        self.watchers = None;
+        if let Some(worker) = self.worker.take() {
+            worker.stopped.store(true, Ordering::SeqCst);
+            let _ = worker.control.send(WatchMessage::Stop);
+            if worker.thread.join().is_err() {
+                eprintln!("The watcher thread panicked");
+            }
+        }
+        let mut report = self.report.lock();
+        report.state = WatcherState::Paused;
+        report.statuses.clear();
+    }
+}
 
+impl Drop for FolderWatcher {
+    fn drop(&mut self) {
+        self.stop();
     }
 }
@@ -1 +1,2 @@
-#include <fpdfview.h>
\ No newline at end of file
+#include <fpdfview.h>
+#include <fpdf_edit.h>
\ No newline at end of file